@@ -40,7 +40,9 @@ stellar contract invoke \
   --admin "$ADMIN_ADDRESS" \
   --min_collateral_percent 120 \
   --auto_complete_window 86400 \
-  --dispute_window 604800
+  --dispute_window 604800 \
+  --fee_bps 100 \
+  --treasury "$ADMIN_ADDRESS"
 
 echo "✅ Contract initialized!"
 echo ""
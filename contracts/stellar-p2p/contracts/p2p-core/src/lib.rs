@@ -1,7 +1,9 @@
 #![no_std]
+#![allow(clippy::too_many_arguments)]
 use soroban_sdk::{
-    contract, contracttype, contractimpl,
-    Env, String, Symbol, symbol_short, Address, Vec
+    auth::{ContractContext, InvokerContractAuthEntry, SubContractInvocation},
+    contract, contractclient, contracterror, contracttype, contractimpl, token, vec,
+    Env, String, Symbol, symbol_short, Address, Vec, Map, Bytes, BytesN, IntoVal, TryFromVal, Val
 };
 
 // ============================================================================
@@ -14,6 +16,117 @@ const TRADE_CNT: Symbol = symbol_short!("TRADE_C");
 const MIN_COLL: Symbol = symbol_short!("MIN_COLL");
 const AUTO_WIN: Symbol = symbol_short!("AUTO_WIN");
 const DISP_WIN: Symbol = symbol_short!("DISP_WIN");
+const DECL_PEN: Symbol = symbol_short!("DECL_PEN");
+const FEE_BPS: Symbol = symbol_short!("FEE_BPS");
+const TREASURY: Symbol = symbol_short!("TREASURY");
+const MIG_CUR: Symbol = symbol_short!("MIG_CUR");
+const REQ_CNT: Symbol = symbol_short!("REQ_C");
+const QUOTE_CNT: Symbol = symbol_short!("QUOTE_C");
+const ORACLE: Symbol = symbol_short!("ORACLE");
+const ORA_AGE: Symbol = symbol_short!("ORA_AGE");
+const YIELD_POOL: Symbol = symbol_short!("POOL");
+const INS_SLASH: Symbol = symbol_short!("INS_SLSH");
+const INS_FEE: Symbol = symbol_short!("INS_FEE");
+
+const BPS_DENOM: u32 = 10_000;
+const DEFAULT_DECLINE_PENALTY: u32 = 50; // Percent of a declined trade's value counted against the vendor
+const MAX_FEE_BPS: u32 = 1_000;          // Protocol fee capped at 10%
+const MAX_PAGE_SIZE: u32 = 50;           // Offers returned per list_offers page
+const MAX_PAGE_SCAN: u32 = 200;          // Offer IDs inspected per list_offers call
+
+// Reputation model
+const REPUTATION_HALF_LIFE: u64 = 180 * 86_400;     // Outcomes lose half their weight every ~6 months
+const REPUTATION_DECIMALS: u32 = 7;                 // Trade values are rescaled to this many decimals
+const NEUTRAL_PRIOR_VOLUME: i128 = 500_0000000;     // New vendors start as if 500 whole units traded at 50%
+const FRAUD_WEIGHT: i128 = 3;                       // Arbiter-confirmed fraud counts triple
+
+const MAX_STARS: u32 = 5;
+const MAX_COMMENT_LEN: u32 = 280;
+const MAX_SEALED_PROOF_LEN: u32 = 1_024;   // Envelope bytes accepted by mark_delivered_sealed
+const MAX_MILESTONES: u32 = 10;           // Milestones per Services offer
+const MAX_QUOTES: u32 = 20;               // Quotes per buyer request, bounds the refund loop
+const REFERENCE_DECIMALS: u32 = 2;        // Reference prices are in USD cents
+const MAX_PAYMENT_OPTIONS: u32 = 5;       // Extra fixed-price tokens per offer
+
+// TTL policies, in ledgers (~5s each): (extend when below threshold, extend to)
+const DAY_IN_LEDGERS: u32 = 17_280;
+const INSTANCE_TTL_THRESHOLD: u32 = 30 * DAY_IN_LEDGERS;
+const INSTANCE_TTL_EXTEND: u32 = 120 * DAY_IN_LEDGERS;
+const OFFER_TTL_THRESHOLD: u32 = 30 * DAY_IN_LEDGERS;
+const OFFER_TTL_EXTEND: u32 = 60 * DAY_IN_LEDGERS;
+const TRADE_TTL_THRESHOLD: u32 = 30 * DAY_IN_LEDGERS;
+const TRADE_TTL_EXTEND: u32 = 120 * DAY_IN_LEDGERS;
+const INDEX_TTL_THRESHOLD: u32 = 30 * DAY_IN_LEDGERS;
+const INDEX_TTL_EXTEND: u32 = 120 * DAY_IN_LEDGERS;
+
+// ============================================================================
+// ERRORS
+// ============================================================================
+
+/// Stable error codes returned by every public entry point.
+/// Contract specs cap an error enum at 50 variants, so prefer a general code
+/// (InvalidArgument, InvalidOfferState, InvalidTradeState) over a narrow new one.
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum P2PError {
+    // Setup & admin
+    AlreadyInitialized = 1,
+    NotInitialized = 2,
+    NotAdmin = 3,
+    NotArbiter = 4,
+    TokenNotSupported = 5,
+    FeeTooHigh = 6,
+    NothingToWithdraw = 9,
+
+    // Offers
+    OfferNotFound = 10,
+    NotOfferOwner = 11,
+    OfferNotActive = 12,            // Offer can't be bought right now
+    InvalidOfferState = 13,         // Offer, request or quote status doesn't allow this action
+    HasActiveTrades = 16,
+    InsufficientCollateral = 17,
+    InsufficientFreeCollateral = 18,
+    InvalidAmount = 20,             // Non-positive price, amount, quantity or units
+    InvalidArgument = 24,           // Out-of-range setting, score, page size or oversized input
+
+    // Trades & disputes
+    TradeNotFound = 30,
+    NotTradeBuyer = 31,
+    NotTradeVendor = 32,
+    NotTradeParty = 33,
+    SelfTrade = 34,
+    InvalidTradeState = 35,         // Trade's status doesn't allow this action
+    CannotDispute = 37,
+    DisputeWindowClosed = 40,
+    DisputeWindowOpen = 41,
+    AutoCompleteWindowOpen = 42,
+    NoCancelRequested = 44,
+    CannotApproveOwnRequest = 45,
+    BuyerNotEligible = 46,
+    AlreadyRated = 48,
+
+    // Delivery proofs
+    ProofMustBeSealed = 51,
+    NoBuyerKey = 52,
+    NoProofCommitment = 54,
+    ProofAlreadyRevealed = 55,
+
+    // Buyer requests
+    RequestNotFound = 60,
+    QuoteNotFound = 61,
+    NotRequestOwner = 62,
+    NotQuoteOwner = 63,
+
+    // Oracle pricing
+    PriceUnavailable = 70,          // No oracle configured, or no usable price for the token
+    StalePrice = 71,
+    SlippageExceeded = 72,
+
+    // Collateral yield
+    PoolNotSet = 80,
+    PoolIlliquid = 81,              // Pool couldn't return supplied collateral; retry later
+}
 
 // ============================================================================
 // ENUMS
@@ -39,6 +152,27 @@ pub enum OfferStatus {
     Paused,      // Vendor temporarily paused
     InTrade,     // Currently in an active trade
     Cancelled,   // Vendor cancelled (no active trades)
+    SoldOut,     // No units left until restocked
+    Expired,     // Swept after expires_at, collateral returned
+}
+
+/// Buyer request lifecycle states
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub enum RequestStatus {
+    Open,        // Taking quotes, budget in escrow
+    Filled,      // A quote was accepted and became a trade
+    Cancelled,   // Buyer withdrew the request
+}
+
+/// Vendor quote lifecycle states
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub enum QuoteStatus {
+    Pending,     // Collateral locked, awaiting the buyer
+    Accepted,    // Became the request's trade
+    Rejected,    // Another quote won or the request was cancelled
+    Withdrawn,   // Vendor pulled the quote
 }
 
 /// Trade lifecycle states
@@ -51,14 +185,16 @@ pub enum TradeStatus {
     DisputedBuyer,    // Buyer initiated dispute
     DisputedVendor,   // Vendor initiated dispute
     Cancelled,        // Cancelled before delivery
+    Resolved,         // Dispute settled by ruling
 }
 
-/// Token types for collateral
-#[contracttype]
-#[derive(Clone, Debug, PartialEq)]
-pub enum TokenType {
-    XLM,
-    USDC,
+/// How a trade ended, for reputation weighting
+#[derive(Clone, Copy, PartialEq)]
+enum TradeOutcome {
+    Completed,   // Delivered, or vindicated in a dispute
+    Cancelled,   // Unwound without a ruling; neutral
+    Defaulted,   // Lost a dispute by not answering it
+    Fraud,       // Arbiter ruled against the vendor
 }
 
 // ============================================================================
@@ -69,11 +205,35 @@ pub enum TokenType {
 #[contracttype]
 #[derive(Clone, Debug)]
 pub struct CollateralInfo {
-    pub amount: i128,              // Amount locked
-    pub token_type: TokenType,      // XLM or USDC
+    pub amount: i128,              // Total amount locked
+    pub reserved: i128,            // Slice reserved by open trades
+    pub token_symbol: String,       // From the token registry, e.g. "USDC"
     pub token_address: Address,     // Token contract address
     pub locked_at: u64,             // Timestamp
-    pub yield_accrued: i128,        // For Blend integration (future)
+    pub yield_accrued: i128,        // Pool interest credited to the vendor so far
+    pub supplied: i128,             // Principal currently in the yield pool
+    pub pool_shares: i128,          // This offer's share of the contract's pool position
+}
+
+/// Registry entry recorded by register_token
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub struct TokenInfo {
+    pub token: Address,
+    pub symbol: String,             // Read from the token contract
+    pub decimals: u32,              // Read from the token contract
+    pub min_collateral_percent: u32, // Collateral floor when locked in this token, 0 = global default
+    pub min_trade: i128,            // Smallest trade payment in this token, 0 = no floor
+    pub max_trade: i128,            // Largest trade payment in this token, 0 = no cap
+    pub registered_at: u64,
+}
+
+/// Extra token an offer can be paid in, at its own fixed per-unit price
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PaymentOption {
+    pub token: Address,
+    pub price: i128,                // Per unit, in this token's smallest unit
 }
 
 /// Vendor offer
@@ -85,12 +245,22 @@ pub struct Offer {
     pub category: Category,
     pub title: String,              // "Amazon $50 Gift Card"
     pub description: String,        // Delivery details
-    pub price: i128,                // In smallest unit (stroops for XLM)
+    pub price: i128,                // Per unit, in smallest unit (stroops for XLM)
     pub payment_token: Address,     // Token contract for payments
+    pub quantity_total: u32,        // Units ever stocked
+    pub quantity_remaining: u32,    // Units still for sale
     pub collateral: CollateralInfo,
     pub status: OfferStatus,
     pub active_trade_count: u32,    // How many active trades
     pub completed_trade_count: u32, // For reputation
+    pub min_buyer_score: u32,       // 0-100 buyer reputation floor, 0 = anyone
+    pub require_clean_buyer: bool,  // Reject buyers who have lost a dispute
+    pub milestones: Vec<u32>,       // Services only: per-milestone share of price in bps, empty = single release
+    pub request_id: u64,            // Buyer request this unlisted offer fills, 0 for listed offers
+    pub expires_at: u64,            // Can't be bought from this time on, 0 = never
+    pub ref_price: i128,            // Per unit in USD cents, converted by the oracle at accept; 0 = use price
+    pub accepted_tokens: Vec<Address>, // Extra tokens a reference-priced offer can be paid in
+    pub payment_options: Vec<PaymentOption>, // Extra tokens at fixed prices; collateral is sized on `price`
     pub created_at: u64,
     pub updated_at: u64,
 }
@@ -103,18 +273,55 @@ pub struct Trade {
     pub offer_id: u64,
     pub buyer: Address,
     pub vendor: Address,
-    pub amount: i128,               // Payment amount
+    pub units: u32,                 // Units bought
+    pub amount: i128,               // Payment amount (price * units)
     pub payment_token: Address,     // Token contract
     pub collateral_amount: i128,    // Locked collateral
+    pub milestones: Vec<u32>,       // Offer's milestone shares when the trade was funded
+    pub milestones_released: u32,   // Milestones confirmed and paid out
+    pub released_amount: i128,      // Payment already paid to the vendor
+    pub released_collateral: i128,  // Reservation already returned to the offer
     pub status: TradeStatus,
-    pub delivery_proof: String,     // PIN/code/tx-hash/url
+    pub delivery_proof: String,     // PIN/code/tx-hash/url, plaintext deliveries only
+    pub buyer_key: Option<BytesN<32>>,        // Buyer's X25519 key; proofs must be sealed to it
+    pub sealed_proof: Bytes,                  // X25519 + ChaCha20-Poly1305 envelope
+    pub proof_commitment: Option<BytesN<32>>, // sha256(proof || salt) of the sealed or committed proof
+    pub revealed_proof: Bytes,                // Preimage disclosed by the vendor in a dispute
+    pub proof_matches: Option<bool>,          // None until revealed, then whether it hashed to the commitment
+    pub insured_loss: i128,         // Ruling's refund the payouts didn't cover, claimable from insurance
+    pub insurance_paid: i128,       // Paid out of the insurance fund so far
+    pub hashlock: Option<BytesN<32>>,         // CrossChain only: sha256 the vendor's claim preimage must match
+    pub preimage: Option<BytesN<32>>,         // Set when the vendor claims; the buyer redeems it on the other chain
     pub created_at: u64,
     pub delivered_at: u64,          // 0 if not delivered
     pub completed_at: u64,          // 0 if not completed
     pub dispute_reason: String,     // Empty if no dispute
+    pub vendor_evidence: String,    // Empty unless vendor counter-disputed
+    pub disputed_at: u64,           // 0 if not disputed
+    pub cancel_requested_by: Option<Address>, // Party awaiting the other's cancel approval
     pub auto_complete_at: u64,      // Timestamp for auto-completion
 }
 
+/// Criteria for list_offers; unset options and empty lists match everything
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct OfferFilter {
+    pub categories: Vec<Category>,    // Any of these categories
+    pub payment_token: Option<Address>,
+    pub min_price: Option<i128>,
+    pub max_price: Option<i128>,
+    pub min_reputation: Option<u32>,  // Vendor reputation_score floor
+    pub statuses: Vec<OfferStatus>,   // Any of these statuses
+}
+
+/// One page of list_offers results
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct OfferPage {
+    pub offers: Vec<Offer>,
+    pub next_cursor: u64,             // Pass back to continue; 0 when exhausted
+}
+
 /// Vendor reputation stats
 #[contracttype]
 #[derive(Clone, Debug)]
@@ -123,34 +330,275 @@ pub struct VendorStats {
     pub total_trades: u32,
     pub completed_trades: u32,
     pub disputed_trades: u32,
+    pub declined_trades: u32,       // Funded trades the vendor turned down
+    pub cancelled_trades: u32,      // Trades unwound without a ruling
+    pub fraud_trades: u32,          // Disputes an arbiter ruled against the vendor
     pub total_volume: i128,         // Total value traded
+    pub positive_volume: i128,      // Decayed value of good outcomes
+    pub negative_volume: i128,      // Decayed, fraud-weighted value of bad outcomes
+    pub neutral_volume: i128,       // Decayed value of cancellations, not scored
+    pub declined_volume: i128,      // Decayed value of declined trades
+    pub rated_volume: i128,         // Decayed value of buyer-rated trades
+    pub rating_credit: i128,        // Decayed share of rated_volume earned by stars
+    pub decayed_at: u64,            // Timestamp the volumes were last decayed to
+    pub rating_count: u32,
+    pub rating_average: u32,        // Mean stars x100 (e.g. 450 = 4.5 stars)
+    pub reputation_score: u32,      // 0-100 score as of the last update
+}
+
+/// Inputs behind a vendor's reputation score, decayed to `as_of`
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct ReputationBreakdown {
+    pub vendor: Address,
+    pub positive_volume: i128,
+    pub negative_volume: i128,
+    pub neutral_volume: i128,
+    pub declined_volume: i128,
+    pub rated_volume: i128,
+    pub rating_credit: i128,
+    pub prior_volume: i128,         // Neutral prior every vendor starts with
+    pub base_score: u32,            // Volume-weighted score before declined trades count
+    pub decline_penalty: u32,       // Points lost to declined trades
+    pub score: u32,
+    pub as_of: u64,
+}
+
+/// Buyer reputation stats
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct BuyerStats {
+    pub buyer: Address,
+    pub total_trades: u32,          // Trades started
+    pub completed_trades: u32,
+    pub disputes_opened: u32,
+    pub disputes_lost: u32,         // Rulings that awarded the buyer nothing
+    pub total_volume: i128,         // Value of completed trades
+    pub rating_count: u32,
+    pub rating_average: u32,        // Mean stars x100 given by vendors
     pub reputation_score: u32,      // 0-100 calculated score
 }
 
+/// Star rating one trade party left for the other
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct Review {
+    pub trade_id: u64,
+    pub rater: Address,
+    pub ratee: Address,
+    pub stars: u32,                 // 1-5
+    pub comment: String,
+    pub created_at: u64,
+}
+
+/// One page of get_vendor_reviews results
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct ReviewPage {
+    pub reviews: Vec<Review>,
+    pub next_cursor: u64,           // Pass back to continue; 0 when exhausted
+}
+
+/// Buyer's request for quotes; the budget is escrowed until filled or cancelled
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct BuyerRequest {
+    pub id: u64,
+    pub buyer: Address,
+    pub category: Category,
+    pub title: String,              // "5000 NGN MTN airtime"
+    pub description: String,        // Delivery details
+    pub payment_token: Address,     // Budget, payment and quote collateral token
+    pub budget: i128,               // Most the buyer will pay, held in escrow
+    pub status: RequestStatus,
+    pub quote_count: u32,           // Quotes ever submitted
+    pub trade_id: u64,              // Trade opened from the accepted quote, 0 until filled
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+/// Vendor's collateral-backed price for a buyer request
+#[contracttype]
+#[derive(Clone, Debug)]
+pub struct Quote {
+    pub id: u64,
+    pub request_id: u64,
+    pub vendor: Address,
+    pub price: i128,                // Total price, at most the request budget
+    pub collateral_amount: i128,    // Locked in the request's payment token
+    pub note: String,               // Terms or delivery time
+    pub status: QuoteStatus,
+    pub offer_id: u64,              // Unlisted offer backing the trade, 0 until accepted
+    pub created_at: u64,
+}
+
+// ============================================================================
+// PRICE ORACLE (SEP-40)
+// ============================================================================
+
+/// Asset identifier used by SEP-40 oracles
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Asset {
+    Stellar(Address),
+    Other(Symbol),
+}
+
+/// Price with the oracle's `decimals()` precision, quoted in its base asset (USD)
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PriceData {
+    pub price: i128,
+    pub timestamp: u64,
+}
+
+/// The subset of the SEP-40 price feed interface the marketplace reads
+#[contractclient(name = "PriceOracleClient")]
+pub trait PriceOracle {
+    fn lastprice(env: Env, asset: Asset) -> Option<PriceData>;
+    fn decimals(env: Env) -> u32;
+}
+
+// ============================================================================
+// YIELD POOL
+// ============================================================================
+
+/// Lending pool idle collateral can be supplied to (Blend-style)
+#[contractclient(name = "YieldPoolClient")]
+pub trait YieldPool {
+    /// Pull `amount` of `token` from `from` and add it to `from`'s position
+    fn supply(env: Env, from: Address, token: Address, amount: i128);
+    /// Send up to `amount` of `from`'s position to `to`; returns what was sent
+    fn withdraw(env: Env, from: Address, token: Address, amount: i128, to: Address) -> i128;
+    /// Current value of `owner`'s position, interest included
+    fn balance(env: Env, owner: Address, token: Address) -> i128;
+}
+
+// ============================================================================
+// LEGACY LAYOUT
+// ============================================================================
+
+// Records as the original build kept them in instance storage. Only
+// migrate_storage reads these, converting each to its current type.
+
+#[contracttype(export = false)]
+#[derive(Clone, Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)] // Variant names are the stored symbols
+enum LegacyTokenType {
+    XLM,
+    USDC,
+}
+
+#[contracttype(export = false)]
+#[derive(Clone, Debug)]
+struct LegacyCollateralInfo {
+    amount: i128,
+    token_type: LegacyTokenType,
+    token_address: Address,
+    locked_at: u64,
+    yield_accrued: i128,
+}
+
+#[contracttype(export = false)]
+#[derive(Clone, Debug)]
+struct LegacyOffer {
+    id: u64,
+    vendor: Address,
+    category: Category,
+    title: String,
+    description: String,
+    price: i128,
+    payment_token: Address,
+    collateral: LegacyCollateralInfo,
+    status: OfferStatus,
+    active_trade_count: u32,
+    completed_trade_count: u32,
+    created_at: u64,
+    updated_at: u64,
+}
+
+#[contracttype(export = false)]
+#[derive(Clone, Debug)]
+struct LegacyTrade {
+    id: u64,
+    offer_id: u64,
+    buyer: Address,
+    vendor: Address,
+    amount: i128,
+    payment_token: Address,
+    collateral_amount: i128,        // The offer's whole collateral at accept time
+    status: TradeStatus,
+    delivery_proof: String,
+    created_at: u64,
+    delivered_at: u64,
+    completed_at: u64,
+    dispute_reason: String,
+    auto_complete_at: u64,
+}
+
+#[contracttype(export = false)]
+#[derive(Clone, Debug)]
+struct LegacyVendorStats {
+    vendor: Address,
+    total_trades: u32,
+    completed_trades: u32,
+    disputed_trades: u32,
+    total_volume: i128,
+    reputation_score: u32,
+}
+
 // ============================================================================
 // STORAGE KEYS
 // ============================================================================
 
-/// Storage key enum following existing pattern
+/// Storage key enum following existing pattern.
+/// Offers, trades, indexes and vendor stats live in persistent storage;
+/// token, arbiter and fee entries are bounded config and stay in instance storage.
 #[contracttype]
 pub enum P2PDataKey {
     // Offers
     Offer(u64),                    // offer_id -> Offer
     VendorOffers(Address),         // vendor -> Vec<u64> offer IDs
-    CategoryOffers(Category),      // category -> Vec<u64> offer IDs
+    CategoryOffers(Category),      // category -> Vec<u64> active offer IDs
     ActiveOffers,                  // Vec<u64> all active offer IDs
+    OfferExpiries,                 // Map<u64, u64> offer_id -> expires_at, for offers that set one
 
     // Trades
     Trade(u64),                    // trade_id -> Trade
     BuyerTrades(Address),          // buyer -> Vec<u64> trade IDs
     VendorTrades(Address),         // vendor -> Vec<u64> trade IDs
     OfferTrades(u64),              // offer_id -> Vec<u64> trade IDs
+    DeliveredTrades,               // Vec<u64> trades awaiting buyer confirmation
+
+    // Buyer requests
+    Request(u64),                  // request_id -> BuyerRequest
+    Quote(u64),                    // quote_id -> Quote
+    BuyerRequests(Address),        // buyer -> Vec<u64> request IDs
+    CategoryRequests(Category),    // category -> Vec<u64> open request IDs
+    RequestQuotes(u64),            // request_id -> Vec<u64> quote IDs
+    VendorQuotes(Address),         // vendor -> Vec<u64> quote IDs
 
     // Reputation
     VendorStats(Address),          // vendor -> VendorStats
+    BuyerStats(Address),           // buyer -> BuyerStats
+    Review(u64, Address),          // (trade_id, rater) -> Review
+    VendorReviews(Address),        // vendor -> Vec<u64> trade IDs rated by buyers
 
     // Token registry (for supported tokens)
-    SupportedToken(Address),       // token_address -> bool
+    SupportedToken(Address),       // token_address -> TokenInfo
+    SupportedTokens,               // Vec<Address> registered tokens, in registration order
+
+    // Dispute resolution
+    Arbiter(Address),              // arbiter -> bool
+
+    // Protocol fees
+    FeeBalance(Address),           // token_address -> uncollected fees
+
+    // Insurance fund
+    InsuranceBalance(Address),     // token_address -> fund balance
+
+    // Collateral yield
+    PoolShares(Address),           // token_address -> total shares of the contract's pool position
 }
 
 // ============================================================================
@@ -174,11 +622,17 @@ impl StellarP2PContract {
         min_collateral_percent: u32,    // e.g., 120 for 120%
         auto_complete_window: u64,       // e.g., 86400 for 24h
         dispute_window: u64,             // e.g., 604800 for 7 days
-    ) {
+        fee_bps: u32,                    // e.g., 100 for 1% of each payment
+        treasury: Address,               // Receives withdrawn fees
+    ) -> Result<(), P2PError> {
         admin.require_auth();
 
         if env.storage().instance().has(&ADMIN) {
-            panic!("Already initialized");
+            return Err(P2PError::AlreadyInitialized);
+        }
+
+        if fee_bps > MAX_FEE_BPS {
+            return Err(P2PError::FeeTooHigh);
         }
 
         env.storage().instance().set(&ADMIN, &admin);
@@ -187,27 +641,254 @@ impl StellarP2PContract {
         env.storage().instance().set(&MIN_COLL, &min_collateral_percent);
         env.storage().instance().set(&AUTO_WIN, &auto_complete_window);
         env.storage().instance().set(&DISP_WIN, &dispute_window);
+        env.storage().instance().set(&FEE_BPS, &fee_bps);
+        env.storage().instance().set(&TREASURY, &treasury);
 
-        env.storage().instance().extend_ttl(5000, 5000);
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        Ok(())
     }
 
-    /// Register a supported payment/collateral token
-    pub fn register_token(env: Env, admin: Address, token: Address) {
+    /// Register a supported payment/collateral token, recording its symbol and
+    /// decimals. Re-registering refreshes the metadata and keeps its limits.
+    pub fn register_token(env: Env, admin: Address, token: Address) -> Result<(), P2PError> {
         admin.require_auth();
 
-        let stored_admin: Address = env.storage().instance()
-            .get(&ADMIN).unwrap_or_else(|| panic!("Not initialized"));
+        Self::require_admin(&env, &admin)?;
+
+        let client = token::TokenClient::new(&env, &token);
+        let info = match Self::token_info(&env, &token) {
+            Some(existing) => TokenInfo {
+                symbol: client.symbol(),
+                decimals: client.decimals(),
+                ..existing
+            },
+            None => Self::add_token(&env, &token),
+        };
+
+        env.storage().instance().set(&P2PDataKey::SupportedToken(token.clone()), &info);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish((symbol_short!("tok_add"), admin), (token, info.symbol));
+
+        Ok(())
+    }
+
+    /// Set a token's collateral floor and trade size limits (0 = no override / no limit)
+    pub fn set_token_limits(
+        env: Env,
+        admin: Address,
+        token: Address,
+        min_collateral_percent: u32,
+        min_trade: i128,
+        max_trade: i128,
+    ) -> Result<(), P2PError> {
+        admin.require_auth();
+
+        Self::require_admin(&env, &admin)?;
+
+        let mut info = Self::load_token_info(&env, &token)?;
 
-        if admin != stored_admin {
-            panic!("Not admin");
+        if min_trade < 0 || max_trade < 0 || (max_trade != 0 && max_trade < min_trade) {
+            return Err(P2PError::InvalidArgument);
         }
 
-        env.storage().instance().set(
-            &P2PDataKey::SupportedToken(token.clone()),
-            &true
+        info.min_collateral_percent = min_collateral_percent;
+        info.min_trade = min_trade;
+        info.max_trade = max_trade;
+        env.storage().instance().set(&P2PDataKey::SupportedToken(token.clone()), &info);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("tok_lim"), admin, token),
+            (min_collateral_percent, min_trade, max_trade)
         );
 
-        env.storage().instance().extend_ttl(5000, 5000);
+        Ok(())
+    }
+
+    /// Stop a token from being used by new offers, requests and trades.
+    /// Trades already funded in it settle normally.
+    pub fn deregister_token(env: Env, admin: Address, token: Address) -> Result<(), P2PError> {
+        admin.require_auth();
+
+        Self::require_admin(&env, &admin)?;
+
+        if !Self::is_supported(&env, &token) {
+            return Err(P2PError::TokenNotSupported);
+        }
+
+        env.storage().instance().remove(&P2PDataKey::SupportedToken(token.clone()));
+
+        let mut tokens = Self::supported_tokens(&env);
+        if let Some(index) = tokens.first_index_of(&token) {
+            tokens.remove(index);
+        }
+        env.storage().instance().set(&P2PDataKey::SupportedTokens, &tokens);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish((symbol_short!("tok_rm"), admin), token);
+
+        Ok(())
+    }
+
+    /// Register an arbiter allowed to rule on disputes
+    pub fn register_arbiter(env: Env, admin: Address, arbiter: Address) -> Result<(), P2PError> {
+        admin.require_auth();
+
+        Self::require_admin(&env, &admin)?;
+
+        env.storage().instance().set(&P2PDataKey::Arbiter(arbiter.clone()), &true);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish((symbol_short!("arb_add"), admin), arbiter);
+
+        Ok(())
+    }
+
+    /// Remove an arbiter
+    pub fn remove_arbiter(env: Env, admin: Address, arbiter: Address) -> Result<(), P2PError> {
+        admin.require_auth();
+
+        Self::require_admin(&env, &admin)?;
+
+        env.storage().instance().remove(&P2PDataKey::Arbiter(arbiter.clone()));
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish((symbol_short!("arb_rm"), admin), arbiter);
+
+        Ok(())
+    }
+
+    /// Point reference-priced offers at a SEP-40 price feed. Prices older than
+    /// `max_age` seconds are refused.
+    pub fn set_oracle(env: Env, admin: Address, oracle: Address, max_age: u64) -> Result<(), P2PError> {
+        admin.require_auth();
+
+        Self::require_admin(&env, &admin)?;
+
+        if max_age == 0 {
+            return Err(P2PError::InvalidArgument);
+        }
+
+        env.storage().instance().set(&ORACLE, &oracle);
+        env.storage().instance().set(&ORA_AGE, &max_age);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish((symbol_short!("oracle"), admin), (oracle, max_age));
+
+        Ok(())
+    }
+
+    /// Set the lending pool vendors can supply idle collateral to
+    pub fn set_yield_pool(env: Env, admin: Address, pool: Address) -> Result<(), P2PError> {
+        admin.require_auth();
+
+        Self::require_admin(&env, &admin)?;
+
+        env.storage().instance().set(&YIELD_POOL, &pool);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish((symbol_short!("pool_set"), admin), pool);
+
+        Ok(())
+    }
+
+    /// Set how heavily declined trades count against a vendor, as a percent
+    /// of their value
+    pub fn set_decline_penalty(env: Env, admin: Address, penalty: u32) -> Result<(), P2PError> {
+        admin.require_auth();
+
+        Self::require_admin(&env, &admin)?;
+
+        if penalty > 100 {
+            return Err(P2PError::InvalidArgument);
+        }
+
+        env.storage().instance().set(&DECL_PEN, &penalty);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        Ok(())
+    }
+
+    /// Update the protocol fee and treasury address
+    pub fn set_fee_config(env: Env, admin: Address, fee_bps: u32, treasury: Address) -> Result<(), P2PError> {
+        admin.require_auth();
+
+        Self::require_admin(&env, &admin)?;
+
+        if fee_bps > MAX_FEE_BPS {
+            return Err(P2PError::FeeTooHigh);
+        }
+
+        env.storage().instance().set(&FEE_BPS, &fee_bps);
+        env.storage().instance().set(&TREASURY, &treasury);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish((symbol_short!("fee_cfg"), admin), (fee_bps, treasury));
+
+        Ok(())
+    }
+
+    /// Route a share of every collateral slash and of protocol fees into the
+    /// insurance fund (basis points of each)
+    pub fn set_insurance_config(
+        env: Env,
+        admin: Address,
+        slash_share_bps: u32,
+        fee_share_bps: u32,
+    ) -> Result<(), P2PError> {
+        admin.require_auth();
+
+        Self::require_admin(&env, &admin)?;
+
+        if slash_share_bps > BPS_DENOM || fee_share_bps > BPS_DENOM {
+            return Err(P2PError::InvalidArgument);
+        }
+
+        env.storage().instance().set(&INS_SLASH, &slash_share_bps);
+        env.storage().instance().set(&INS_FEE, &fee_share_bps);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish((symbol_short!("ins_cfg"), admin), (slash_share_bps, fee_share_bps));
+
+        Ok(())
+    }
+
+    /// Sweep collected fees for a token to the treasury
+    pub fn withdraw_fees(env: Env, admin: Address, token: Address) -> Result<i128, P2PError> {
+        admin.require_auth();
+
+        Self::require_admin(&env, &admin)?;
+
+        let treasury: Address = env.storage().instance().get(&TREASURY).ok_or(P2PError::NotInitialized)?;
+
+        let collected: i128 = env.storage().instance()
+            .get(&P2PDataKey::FeeBalance(token.clone()))
+            .unwrap_or(0);
+
+        if collected == 0 {
+            return Err(P2PError::NothingToWithdraw);
+        }
+
+        Self::transfer_tokens(env.clone(), &token, &treasury, &collected);
+        env.storage().instance().set(&P2PDataKey::FeeBalance(token.clone()), &0i128);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish((symbol_short!("fee_wd"), token, treasury), collected);
+
+        Ok(collected)
     }
 
     // ========================================================================
@@ -222,27 +903,25 @@ impl StellarP2PContract {
         title: String,
         description: String,
         price: i128,
+        quantity: u32,
         payment_token: Address,
         collateral_amount: i128,
         collateral_token: Address,
-    ) -> u64 {
+    ) -> Result<u64, P2PError> {
         vendor.require_auth();
 
-        // Validate collateral meets minimum requirement
-        let min_percent: u32 = env.storage().instance().get(&MIN_COLL).unwrap();
-        let min_collateral = (price * min_percent as i128) / 100;
-
-        if collateral_amount < min_collateral {
-            panic!("Insufficient collateral");
+        if quantity == 0 {
+            return Err(P2PError::InvalidAmount);
         }
 
-        // Verify token is supported
-        let is_supported: bool = env.storage().instance()
-            .get(&P2PDataKey::SupportedToken(collateral_token.clone()))
-            .unwrap_or(false);
+        // Validate collateral covers the whole stock
+        if collateral_amount < Self::required_collateral(&env, &collateral_token, price * quantity as i128)? {
+            return Err(P2PError::InsufficientCollateral);
+        }
 
-        if !is_supported {
-            panic!("Token not supported");
+        // Verify both tokens are supported
+        if !Self::is_supported(&env, &collateral_token) || !Self::is_supported(&env, &payment_token) {
+            return Err(P2PError::TokenNotSupported);
         }
 
         // Transfer collateral from vendor to contract
@@ -258,61 +937,55 @@ impl StellarP2PContract {
         let mut offer_count: u64 = env.storage().instance().get(&OFFER_CNT).unwrap_or(0);
         offer_count += 1;
 
-        let token_type = if collateral_token == payment_token {
-            TokenType::USDC
-        } else {
-            TokenType::XLM
-        };
+        let token_symbol = Self::load_token_info(&env, &collateral_token)?.symbol;
 
         // Create offer
         let offer = Offer {
             id: offer_count,
             vendor: vendor.clone(),
-            category: category.clone(),
+            category,
             title,
             description,
             price,
             payment_token,
+            quantity_total: quantity,
+            quantity_remaining: quantity,
             collateral: CollateralInfo {
                 amount: collateral_amount,
-                token_type,
+                reserved: 0,
+                token_symbol,
                 token_address: collateral_token,
                 locked_at: env.ledger().timestamp(),
                 yield_accrued: 0,
+                supplied: 0,
+                pool_shares: 0,
             },
             status: OfferStatus::Active,
             active_trade_count: 0,
             completed_trade_count: 0,
+            min_buyer_score: 0,
+            require_clean_buyer: false,
+            milestones: Vec::new(&env),
+            request_id: 0,
+            expires_at: 0,
+            ref_price: 0,
+            accepted_tokens: Vec::new(&env),
+            payment_options: Vec::new(&env),
             created_at: env.ledger().timestamp(),
             updated_at: env.ledger().timestamp(),
         };
 
         // Store offer
-        env.storage().instance().set(&P2PDataKey::Offer(offer_count), &offer);
+        Self::save_offer(&env, &offer);
         env.storage().instance().set(&OFFER_CNT, &offer_count);
 
         // Add to vendor's offers
-        let mut vendor_offers: Vec<u64> = env.storage().instance()
-            .get(&P2PDataKey::VendorOffers(vendor.clone()))
-            .unwrap_or(Vec::new(&env));
-        vendor_offers.push_back(offer_count);
-        env.storage().instance().set(&P2PDataKey::VendorOffers(vendor.clone()), &vendor_offers);
-
-        // Add to category offers
-        let mut category_offers: Vec<u64> = env.storage().instance()
-            .get(&P2PDataKey::CategoryOffers(category.clone()))
-            .unwrap_or(Vec::new(&env));
-        category_offers.push_back(offer_count);
-        env.storage().instance().set(&P2PDataKey::CategoryOffers(category), &category_offers);
-
-        // Add to active offers
-        let mut active_offers: Vec<u64> = env.storage().instance()
-            .get(&P2PDataKey::ActiveOffers)
-            .unwrap_or(Vec::new(&env));
-        active_offers.push_back(offer_count);
-        env.storage().instance().set(&P2PDataKey::ActiveOffers, &active_offers);
-
-        env.storage().instance().extend_ttl(5000, 5000);
+        Self::add_to_index(&env, &P2PDataKey::VendorOffers(vendor.clone()), offer_count);
+
+        // Add to category and active offers
+        Self::list_offer(&env, &offer);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
 
         // Emit event
         env.events().publish(
@@ -320,25 +993,29 @@ impl StellarP2PContract {
             offer_count
         );
 
-        offer_count
+        Ok(offer_count)
     }
 
     /// Cancel an offer (only if no active trades)
-    pub fn cancel_offer(env: Env, vendor: Address, offer_id: u64) {
+    pub fn cancel_offer(env: Env, vendor: Address, offer_id: u64) -> Result<(), P2PError> {
         vendor.require_auth();
 
-        let mut offer: Offer = env.storage().instance()
-            .get(&P2PDataKey::Offer(offer_id))
-            .unwrap_or_else(|| panic!("Offer not found"));
+        let mut offer: Offer = Self::load_offer(&env, offer_id)?;
 
         if offer.vendor != vendor {
-            panic!("Not offer owner");
+            return Err(P2PError::NotOfferOwner);
+        }
+
+        if offer.status == OfferStatus::Cancelled || offer.status == OfferStatus::Expired {
+            return Err(P2PError::InvalidOfferState);
         }
 
         if offer.active_trade_count > 0 {
-            panic!("Has active trades");
+            return Err(P2PError::HasActiveTrades);
         }
 
+        Self::recall_from_pool(&env, &mut offer)?;
+
         // Return collateral to vendor
         Self::transfer_tokens(
             env.clone(),
@@ -350,419 +1027,3123 @@ impl StellarP2PContract {
         offer.status = OfferStatus::Cancelled;
         offer.updated_at = env.ledger().timestamp();
 
-        env.storage().instance().set(&P2PDataKey::Offer(offer_id), &offer);
-
-        // Remove from active offers
-        let active_offers: Vec<u64> = env.storage().instance()
-            .get(&P2PDataKey::ActiveOffers)
-            .unwrap_or(Vec::new(&env));
-        let mut new_active: Vec<u64> = Vec::new(&env);
-        for i in 0..active_offers.len() {
-            let id = active_offers.get(i).unwrap();
-            if id != offer_id {
-                new_active.push_back(id);
-            }
-        }
-        env.storage().instance().set(&P2PDataKey::ActiveOffers, &new_active);
+        Self::save_offer(&env, &offer);
+
+        // Remove from listing indexes
+        Self::unlist_offer(&env, &offer);
+        Self::record_expiry(&env, offer_id, 0);
 
-        env.storage().instance().extend_ttl(5000, 5000);
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
 
         env.events().publish(
             (symbol_short!("offer_cnl"), vendor),
             offer_id
         );
+
+        Ok(())
     }
 
-    // ========================================================================
-    // TRADE EXECUTION
-    // ========================================================================
+    /// Temporarily hide an offer from buyers; funded trades continue normally
+    pub fn pause_offer(env: Env, vendor: Address, offer_id: u64) -> Result<(), P2PError> {
+        vendor.require_auth();
 
-    /// Buyer accepts offer and creates trade
-    pub fn accept_offer(
+        let mut offer: Offer = Self::load_offer(&env, offer_id)?;
+
+        if offer.vendor != vendor {
+            return Err(P2PError::NotOfferOwner);
+        }
+
+        if offer.status != OfferStatus::Active {
+            return Err(P2PError::OfferNotActive);
+        }
+
+        offer.status = OfferStatus::Paused;
+        offer.updated_at = env.ledger().timestamp();
+        Self::save_offer(&env, &offer);
+
+        Self::unlist_offer(&env, &offer);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("offer_pse"), vendor),
+            offer_id
+        );
+
+        Ok(())
+    }
+
+    /// Put a paused offer back on the market
+    pub fn resume_offer(env: Env, vendor: Address, offer_id: u64) -> Result<(), P2PError> {
+        vendor.require_auth();
+
+        let mut offer: Offer = Self::load_offer(&env, offer_id)?;
+
+        if offer.vendor != vendor {
+            return Err(P2PError::NotOfferOwner);
+        }
+
+        if offer.status != OfferStatus::Paused {
+            return Err(P2PError::InvalidOfferState);
+        }
+
+        offer.status = OfferStatus::Active;
+        offer.updated_at = env.ledger().timestamp();
+        Self::save_offer(&env, &offer);
+
+        Self::list_offer(&env, &offer);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("offer_res"), vendor),
+            offer_id
+        );
+
+        Ok(())
+    }
+
+    /// Price an offer per unit in USD cents instead of a fixed token amount.
+    /// Buyers can pay in the offer's payment token or any of `accepted_tokens`,
+    /// converted at the oracle price when they accept. 0 restores fixed pricing.
+    pub fn set_reference_price(
+        env: Env,
+        vendor: Address,
+        offer_id: u64,
+        ref_price: i128,
+        accepted_tokens: Vec<Address>,
+    ) -> Result<(), P2PError> {
+        vendor.require_auth();
+
+        let mut offer: Offer = Self::load_offer(&env, offer_id)?;
+
+        if offer.vendor != vendor {
+            return Err(P2PError::NotOfferOwner);
+        }
+
+        if !Self::is_open(&offer) || offer.request_id != 0 {
+            return Err(P2PError::InvalidOfferState);
+        }
+
+        if ref_price < 0 {
+            return Err(P2PError::InvalidAmount);
+        }
+
+        for token in accepted_tokens.iter() {
+            if !Self::is_supported(&env, &token) {
+                return Err(P2PError::TokenNotSupported);
+            }
+        }
+
+        offer.ref_price = ref_price;
+        offer.accepted_tokens = accepted_tokens;
+
+        // Remaining stock must stay collateralised at today's oracle price
+        if Self::free_collateral(&offer) < Self::stock_collateral(&env, &offer)? {
+            return Err(P2PError::InsufficientCollateral);
+        }
+
+        offer.updated_at = env.ledger().timestamp();
+        Self::save_offer(&env, &offer);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("ref_price"), vendor),
+            (offer_id, ref_price)
+        );
+
+        Ok(())
+    }
+
+    /// Let buyers pay in other registered tokens, each at its own per-unit price.
+    /// Replaces the previous list; an empty list leaves only `payment_token`.
+    pub fn set_payment_options(
+        env: Env,
+        vendor: Address,
+        offer_id: u64,
+        options: Vec<PaymentOption>,
+    ) -> Result<(), P2PError> {
+        vendor.require_auth();
+
+        let mut offer: Offer = Self::load_offer(&env, offer_id)?;
+
+        if offer.vendor != vendor {
+            return Err(P2PError::NotOfferOwner);
+        }
+
+        if !Self::is_open(&offer) || offer.request_id != 0 {
+            return Err(P2PError::InvalidOfferState);
+        }
+
+        if options.len() > MAX_PAYMENT_OPTIONS {
+            return Err(P2PError::InvalidArgument);
+        }
+
+        let mut seen: Vec<Address> = Vec::new(&env);
+        for option in options.iter() {
+            if option.price <= 0 {
+                return Err(P2PError::InvalidAmount);
+            }
+            if option.token == offer.payment_token || seen.contains(&option.token) {
+                return Err(P2PError::InvalidArgument);
+            }
+            if !Self::is_supported(&env, &option.token) {
+                return Err(P2PError::TokenNotSupported);
+            }
+            seen.push_back(option.token);
+        }
+
+        offer.payment_options = options;
+        offer.updated_at = env.ledger().timestamp();
+        Self::save_offer(&env, &offer);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("pay_opts"), vendor),
+            (offer_id, seen)
+        );
+
+        Ok(())
+    }
+
+    /// Set when the offer stops selling (0 clears it). Funded trades are unaffected;
+    /// once they settle, expire_offers retires the offer and returns its collateral.
+    pub fn set_offer_expiry(env: Env, vendor: Address, offer_id: u64, expires_at: u64) -> Result<(), P2PError> {
+        vendor.require_auth();
+
+        let mut offer: Offer = Self::load_offer(&env, offer_id)?;
+
+        if offer.vendor != vendor {
+            return Err(P2PError::NotOfferOwner);
+        }
+
+        if !Self::is_open(&offer) {
+            return Err(P2PError::InvalidOfferState);
+        }
+
+        if expires_at != 0 && expires_at <= env.ledger().timestamp() {
+            return Err(P2PError::InvalidArgument);
+        }
+
+        offer.expires_at = expires_at;
+        offer.updated_at = env.ledger().timestamp();
+        Self::save_offer(&env, &offer);
+        Self::record_expiry(&env, offer_id, expires_at);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("offer_ttl"), vendor),
+            (offer_id, expires_at)
+        );
+
+        Ok(())
+    }
+
+    /// Keeper sweep: inspect up to `limit` offers after ID `cursor` and retire
+    /// the open ones (active, paused or sold out) past their expiry with no active
+    /// trades, returning their collateral to the vendor. Returns the number expired
+    /// and the cursor for the next call, 0 once the last offer was inspected.
+    pub fn expire_offers(env: Env, cursor: u64, limit: u32) -> Result<(u32, u64), P2PError> {
+        let offer_count: u64 = env.storage().instance().get(&OFFER_CNT).unwrap_or(0);
+        let scan_end = offer_count.min(cursor.saturating_add(limit as u64));
+
+        let mut expired: u32 = 0;
+        let mut last = cursor;
+
+        while last < scan_end {
+            last += 1;
+            let mut offer: Offer = Self::load_offer(&env, last)?;
+
+            // Offers whose pool can't pay out yet are retried on a later sweep
+            if !Self::is_open(&offer)
+                || !Self::is_expired(&env, &offer)
+                || offer.active_trade_count > 0
+                || Self::recall_from_pool(&env, &mut offer).is_err()
+            {
+                continue;
+            }
+
+            Self::transfer_tokens(
+                env.clone(),
+                &offer.collateral.token_address,
+                &offer.vendor,
+                &offer.collateral.amount,
+            );
+
+            offer.status = OfferStatus::Expired;
+            offer.updated_at = env.ledger().timestamp();
+            Self::save_offer(&env, &offer);
+
+            Self::unlist_offer(&env, &offer);
+            Self::record_expiry(&env, offer.id, 0);
+
+            env.events().publish(
+                (symbol_short!("offer_exp"), offer.vendor.clone()),
+                (offer.id, offer.collateral.amount)
+            );
+
+            expired += 1;
+        }
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        let next_cursor = if last < offer_count { last } else { 0 };
+
+        Ok((expired, next_cursor))
+    }
+
+    /// Edit listing details; existing trades keep the terms they were funded at
+    pub fn update_offer(
+        env: Env,
+        vendor: Address,
+        offer_id: u64,
+        title: String,
+        description: String,
+        price: i128,
+    ) -> Result<(), P2PError> {
+        vendor.require_auth();
+
+        let mut offer: Offer = Self::load_offer(&env, offer_id)?;
+
+        if offer.vendor != vendor {
+            return Err(P2PError::NotOfferOwner);
+        }
+
+        if !Self::is_open(&offer) {
+            return Err(P2PError::InvalidOfferState);
+        }
+
+        if price <= 0 {
+            return Err(P2PError::InvalidAmount);
+        }
+
+        offer.title = title;
+        offer.description = description;
+        offer.price = price;
+        offer.updated_at = env.ledger().timestamp();
+
+        // Locked collateral must still cover open trades plus remaining stock on the new terms
+        if Self::free_collateral(&offer) < Self::stock_collateral(&env, &offer)? {
+            return Err(P2PError::InsufficientCollateral);
+        }
+        Self::save_offer(&env, &offer);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("offer_upd"), vendor),
+            (offer_id, price)
+        );
+
+        Ok(())
+    }
+
+    /// Split a Services offer's payment into staged milestones (bps shares summing
+    /// to 100%). Each delivery/confirmation then releases one slice. Funded trades
+    /// keep the milestones they started with; an empty list restores single release.
+    pub fn set_milestones(env: Env, vendor: Address, offer_id: u64, shares: Vec<u32>) -> Result<(), P2PError> {
+        vendor.require_auth();
+
+        let mut offer: Offer = Self::load_offer(&env, offer_id)?;
+
+        if offer.vendor != vendor {
+            return Err(P2PError::NotOfferOwner);
+        }
+
+        if !Self::is_open(&offer) {
+            return Err(P2PError::InvalidOfferState);
+        }
+
+        if offer.category != Category::Services || shares.len() > MAX_MILESTONES {
+            return Err(P2PError::InvalidArgument);
+        }
+
+        let mut total: u32 = 0;
+        for share in shares.iter() {
+            if share == 0 {
+                return Err(P2PError::InvalidArgument);
+            }
+            total += share;
+        }
+        if !shares.is_empty() && total != BPS_DENOM {
+            return Err(P2PError::InvalidArgument);
+        }
+
+        offer.milestones = shares.clone();
+        offer.updated_at = env.ledger().timestamp();
+        Self::save_offer(&env, &offer);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("mstones"), vendor),
+            (offer_id, shares.len())
+        );
+
+        Ok(())
+    }
+
+    /// Restrict who may buy from an offer by buyer reputation
+    pub fn set_buyer_requirements(
+        env: Env,
+        vendor: Address,
+        offer_id: u64,
+        min_buyer_score: u32,
+        require_clean_buyer: bool,
+    ) -> Result<(), P2PError> {
+        vendor.require_auth();
+
+        let mut offer: Offer = Self::load_offer(&env, offer_id)?;
+
+        if offer.vendor != vendor {
+            return Err(P2PError::NotOfferOwner);
+        }
+
+        if min_buyer_score > 100 {
+            return Err(P2PError::InvalidArgument);
+        }
+
+        offer.min_buyer_score = min_buyer_score;
+        offer.require_clean_buyer = require_clean_buyer;
+        offer.updated_at = env.ledger().timestamp();
+        Self::save_offer(&env, &offer);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("buyer_req"), vendor),
+            (offer_id, min_buyer_score, require_clean_buyer)
+        );
+
+        Ok(())
+    }
+
+    /// Lock extra collateral on a live offer
+    pub fn add_collateral(env: Env, vendor: Address, offer_id: u64, amount: i128) -> Result<(), P2PError> {
+        vendor.require_auth();
+
+        let mut offer: Offer = Self::load_offer(&env, offer_id)?;
+
+        if offer.vendor != vendor {
+            return Err(P2PError::NotOfferOwner);
+        }
+
+        if !Self::is_open(&offer) {
+            return Err(P2PError::InvalidOfferState);
+        }
+
+        if amount <= 0 {
+            return Err(P2PError::InvalidAmount);
+        }
+
+        Self::transfer_from(
+            env.clone(),
+            &offer.collateral.token_address,
+            &vendor,
+            &env.current_contract_address(),
+            &amount,
+        );
+
+        offer.collateral.amount += amount;
+        offer.updated_at = env.ledger().timestamp();
+        Self::save_offer(&env, &offer);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("coll_add"), vendor, offer_id),
+            (amount, offer.collateral.amount, Self::free_collateral(&offer))
+        );
+
+        Ok(())
+    }
+
+    /// Withdraw collateral not reserved by open trades
+    pub fn withdraw_free_collateral(env: Env, vendor: Address, offer_id: u64, amount: i128) -> Result<(), P2PError> {
+        vendor.require_auth();
+
+        let mut offer: Offer = Self::load_offer(&env, offer_id)?;
+
+        if offer.vendor != vendor {
+            return Err(P2PError::NotOfferOwner);
+        }
+
+        if !Self::is_open(&offer) {
+            return Err(P2PError::InvalidOfferState);
+        }
+
+        if amount <= 0 {
+            return Err(P2PError::InvalidAmount);
+        }
+
+        if amount > Self::free_collateral(&offer) {
+            return Err(P2PError::InsufficientFreeCollateral);
+        }
+
+        // Remaining stock must stay fully collateralised
+        if Self::free_collateral(&offer) - amount < Self::stock_collateral(&env, &offer)? {
+            return Err(P2PError::InsufficientCollateral);
+        }
+
+        Self::ensure_liquid(&env, &mut offer, amount)?;
+
+        Self::transfer_tokens(
+            env.clone(),
+            &offer.collateral.token_address,
+            &vendor,
+            &amount,
+        );
+
+        offer.collateral.amount -= amount;
+        offer.updated_at = env.ledger().timestamp();
+        Self::save_offer(&env, &offer);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("coll_wd"), vendor, offer_id),
+            (amount, offer.collateral.amount, Self::free_collateral(&offer))
+        );
+
+        Ok(())
+    }
+
+    /// Supply idle collateral to the yield pool. It is recalled automatically
+    /// before any payout that needs it, and the interest is credited to the
+    /// offer's collateral.
+    pub fn supply_collateral(env: Env, vendor: Address, offer_id: u64, amount: i128) -> Result<(), P2PError> {
+        vendor.require_auth();
+
+        let mut offer: Offer = Self::load_offer(&env, offer_id)?;
+
+        if offer.vendor != vendor {
+            return Err(P2PError::NotOfferOwner);
+        }
+
+        if !Self::is_open(&offer) {
+            return Err(P2PError::InvalidOfferState);
+        }
+
+        if amount <= 0 {
+            return Err(P2PError::InvalidAmount);
+        }
+
+        if amount > Self::free_collateral(&offer) - offer.collateral.supplied {
+            return Err(P2PError::InsufficientFreeCollateral);
+        }
+
+        let pool_address: Address = env.storage().instance().get(&YIELD_POOL).ok_or(P2PError::PoolNotSet)?;
+        let pool = YieldPoolClient::new(&env, &pool_address);
+        let token = offer.collateral.token_address.clone();
+        let this = env.current_contract_address();
+
+        // New shares are priced against the position before this deposit
+        let shares_key = P2PDataKey::PoolShares(token.clone());
+        let total_shares: i128 = env.storage().instance().get(&shares_key).unwrap_or(0);
+        let position = pool.balance(&this, &token);
+        let shares = if total_shares == 0 || position == 0 {
+            amount
+        } else {
+            amount * total_shares / position
+        };
+
+        Self::supply_to_pool(&env, &pool_address, &token, amount);
+
+        env.storage().instance().set(&shares_key, &(total_shares + shares));
+        offer.collateral.supplied += amount;
+        offer.collateral.pool_shares += shares;
+        offer.updated_at = env.ledger().timestamp();
+        Self::save_offer(&env, &offer);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("supplied"), vendor, offer_id),
+            (amount, shares)
+        );
+
+        Ok(())
+    }
+
+    /// Pull an offer's collateral back from the yield pool, crediting the interest
+    pub fn recall_collateral(env: Env, vendor: Address, offer_id: u64) -> Result<i128, P2PError> {
+        vendor.require_auth();
+
+        let mut offer: Offer = Self::load_offer(&env, offer_id)?;
+
+        if offer.vendor != vendor {
+            return Err(P2PError::NotOfferOwner);
+        }
+
+        if offer.collateral.pool_shares == 0 {
+            return Err(P2PError::NothingToWithdraw);
+        }
+
+        let earned = Self::recall_from_pool(&env, &mut offer)?;
+        offer.updated_at = env.ledger().timestamp();
+        Self::save_offer(&env, &offer);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        Ok(earned)
+    }
+
+    /// Add units to an offer, locking the matching extra collateral
+    pub fn restock_offer(env: Env, vendor: Address, offer_id: u64, units: u32) -> Result<(), P2PError> {
+        vendor.require_auth();
+
+        let mut offer: Offer = Self::load_offer(&env, offer_id)?;
+
+        if offer.vendor != vendor {
+            return Err(P2PError::NotOfferOwner);
+        }
+
+        // Offers backing a buyer request are single-use
+        if !Self::is_open(&offer) || offer.request_id != 0 {
+            return Err(P2PError::InvalidOfferState);
+        }
+
+        if units == 0 {
+            return Err(P2PError::InvalidAmount);
+        }
+
+        let extra_collateral = Self::required_collateral(
+            &env,
+            &offer.collateral.token_address,
+            Self::collateral_value(&env, &offer, units)?,
+        )?;
+        Self::transfer_from(
+            env.clone(),
+            &offer.collateral.token_address,
+            &vendor,
+            &env.current_contract_address(),
+            &extra_collateral,
+        );
+
+        offer.collateral.amount += extra_collateral;
+        offer.quantity_total += units;
+        offer.quantity_remaining += units;
+        offer.updated_at = env.ledger().timestamp();
+
+        if offer.status == OfferStatus::SoldOut {
+            offer.status = OfferStatus::Active;
+            Self::list_offer(&env, &offer);
+        }
+        Self::save_offer(&env, &offer);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("restock"), vendor, offer_id),
+            (units, extra_collateral)
+        );
+
+        Ok(())
+    }
+
+    // ========================================================================
+    // TRADE EXECUTION
+    // ========================================================================
+
+    /// Buyer accepts offer and creates trade.
+    /// Passing an X25519 `buyer_key` requires the vendor to deliver a sealed proof.
+    pub fn accept_offer(
+        env: Env,
+        buyer: Address,
+        offer_id: u64,
+        units: u32,
+        buyer_key: Option<BytesN<32>>,
+        hashlock: Option<BytesN<32>>,
+        payment_token: Option<Address>,   // None = the offer's payment token
+        max_payment: Option<i128>,        // Slippage guard for reference-priced offers
+    ) -> Result<u64, P2PError> {
+        buyer.require_auth();
+
+        let mut offer: Offer = Self::load_offer(&env, offer_id)?;
+
+        // CrossChain trades settle by hashlock, and only they do
+        if hashlock.is_some() != (offer.category == Category::CrossChain) {
+            return Err(P2PError::InvalidArgument);
+        }
+
+        if offer.status != OfferStatus::Active || Self::is_expired(&env, &offer) {
+            return Err(P2PError::OfferNotActive);
+        }
+
+        if offer.vendor == buyer {
+            return Err(P2PError::SelfTrade);
+        }
+
+        if units == 0 || units > offer.quantity_remaining {
+            return Err(P2PError::InvalidAmount);
+        }
+
+        let buyer_stats = Self::load_buyer_stats(&env, buyer.clone());
+        if buyer_stats.reputation_score < offer.min_buyer_score
+            || (offer.require_clean_buyer && buyer_stats.disputes_lost > 0)
+        {
+            return Err(P2PError::BuyerNotEligible);
+        }
+
+        let payment_token = payment_token.unwrap_or(offer.payment_token.clone());
+        if !Self::is_supported(&env, &payment_token) {
+            return Err(P2PError::TokenNotSupported);
+        }
+
+        let amount = Self::payment_amount(&env, &offer, &payment_token, units)?;
+
+        if max_payment.is_some_and(|max| amount > max) {
+            return Err(P2PError::SlippageExceeded);
+        }
+
+        // Reserve this trade's slice of the offer collateral
+        Self::check_trade_limits(&env, &payment_token, amount)?;
+
+        let reservation = Self::required_collateral(
+            &env,
+            &offer.collateral.token_address,
+            Self::collateral_value(&env, &offer, units)?,
+        )?;
+        if Self::free_collateral(&offer) < reservation {
+            return Err(P2PError::InsufficientFreeCollateral);
+        }
+
+        // Transfer payment from buyer to contract (escrow)
+        Self::transfer_from(
+            env.clone(),
+            &payment_token,
+            &buyer,
+            &env.current_contract_address(),
+            &amount,
+        );
+
+        Self::open_trade(&env, &mut offer, buyer, units, amount, payment_token, reservation, buyer_key, hashlock)
+    }
+
+    /// Vendor marks trade as delivered with proof
+    pub fn mark_delivered(
+        env: Env,
+        vendor: Address,
+        trade_id: u64,
+        delivery_proof: String,
+    ) -> Result<(), P2PError> {
+        vendor.require_auth();
+
+        let mut trade = Self::load_deliverable(&env, &vendor, trade_id)?;
+
+        if trade.buyer_key.is_some() {
+            return Err(P2PError::ProofMustBeSealed);
+        }
+
+        trade.delivery_proof = delivery_proof;
+        Self::record_delivery(env, trade)
+    }
+
+    /// Vendor delivers a proof sealed to the buyer's key, plus a commitment
+    /// arbiters can check a later-disclosed plaintext against
+    pub fn mark_delivered_sealed(
+        env: Env,
+        vendor: Address,
+        trade_id: u64,
+        sealed_proof: Bytes,
+        proof_commitment: BytesN<32>,
+    ) -> Result<(), P2PError> {
+        vendor.require_auth();
+
+        let mut trade = Self::load_deliverable(&env, &vendor, trade_id)?;
+
+        if trade.buyer_key.is_none() {
+            return Err(P2PError::NoBuyerKey);
+        }
+
+        if sealed_proof.len() > MAX_SEALED_PROOF_LEN {
+            return Err(P2PError::InvalidArgument);
+        }
+
+        trade.sealed_proof = sealed_proof;
+        trade.proof_commitment = Some(proof_commitment);
+        Self::record_delivery(env, trade)
+    }
+
+    /// Vendor records only sha256(proof || salt); the proof itself goes to the
+    /// buyer off-chain and is revealed with reveal_proof if a dispute opens
+    pub fn mark_delivered_committed(
+        env: Env,
+        vendor: Address,
+        trade_id: u64,
+        proof_commitment: BytesN<32>,
+    ) -> Result<(), P2PError> {
+        vendor.require_auth();
+
+        let mut trade = Self::load_deliverable(&env, &vendor, trade_id)?;
+
+        if trade.buyer_key.is_some() {
+            return Err(P2PError::ProofMustBeSealed);
+        }
+
+        trade.proof_commitment = Some(proof_commitment);
+        Self::record_delivery(env, trade)
+    }
+
+    /// Vendor claims a hashlocked trade's payment by revealing the preimage,
+    /// which the buyer then uses to redeem on the other chain. The reservation
+    /// stays locked until the buyer confirms or the inspection window lapses,
+    /// so a failed redemption can still be disputed.
+    pub fn claim_htlc(
+        env: Env,
+        vendor: Address,
+        trade_id: u64,
+        preimage: BytesN<32>,
+    ) -> Result<(), P2PError> {
+        vendor.require_auth();
+
+        let mut trade: Trade = Self::load_trade(&env, trade_id)?;
+
+        if trade.vendor != vendor {
+            return Err(P2PError::NotTradeVendor);
+        }
+
+        let hashlock = match &trade.hashlock {
+            Some(hashlock) if trade.status == TradeStatus::Funded => hashlock.clone(),
+            _ => return Err(P2PError::InvalidTradeState),
+        };
+
+        // Past the timelock the funds belong to the buyer's refund
+        if env.ledger().timestamp() > trade.auto_complete_at {
+            return Err(P2PError::InvalidTradeState);
+        }
+
+        if env.crypto().sha256(&Bytes::from(preimage.clone())).to_bytes() != hashlock {
+            return Err(P2PError::InvalidArgument);
+        }
+
+        let (vendor_payment, fee) = Self::pay_vendor(&env, &trade, trade.amount);
+        trade.released_amount = trade.amount;
+        trade.preimage = Some(preimage.clone());
+
+        env.events().publish(
+            (symbol_short!("htlc_clm"), trade.buyer.clone(), vendor),
+            (trade_id, preimage, vendor_payment, fee)
+        );
+
+        Self::record_delivery(env, trade)
+    }
+
+    /// Buyer reclaims a hashlocked trade the vendor didn't claim before the timelock
+    pub fn refund_htlc(env: Env, buyer: Address, trade_id: u64) -> Result<(), P2PError> {
+        buyer.require_auth();
+
+        let trade: Trade = Self::load_trade(&env, trade_id)?;
+
+        if trade.buyer != buyer {
+            return Err(P2PError::NotTradeBuyer);
+        }
+
+        if trade.hashlock.is_none() || trade.status != TradeStatus::Funded {
+            return Err(P2PError::InvalidTradeState);
+        }
+
+        if env.ledger().timestamp() <= trade.auto_complete_at {
+            return Err(P2PError::AutoCompleteWindowOpen);
+        }
+
+        Self::cancel_trade(env, trade)
+    }
+
+    /// Buyer confirms delivery - releases payment and collateral
+    pub fn confirm_delivery(
+        env: Env,
+        buyer: Address,
+        trade_id: u64,
+    ) -> Result<(), P2PError> {
+        buyer.require_auth();
+
+        let trade: Trade = Self::load_trade(&env, trade_id)?;
+
+        if trade.buyer != buyer {
+            return Err(P2PError::NotTradeBuyer);
+        }
+
+        if trade.status != TradeStatus::Delivered {
+            return Err(P2PError::InvalidTradeState);
+        }
+
+        Self::settle_delivery(env, trade, symbol_short!("completed"))
+    }
+
+    /// Anyone can settle a delivered trade for the vendor once the buyer's window lapsed
+    pub fn claim_auto_complete(env: Env, trade_id: u64) -> Result<(), P2PError> {
+        let trade: Trade = Self::load_trade(&env, trade_id)?;
+
+        if trade.status != TradeStatus::Delivered {
+            return Err(P2PError::InvalidTradeState);
+        }
+
+        if env.ledger().timestamp() <= trade.auto_complete_at {
+            return Err(P2PError::AutoCompleteWindowOpen);
+        }
+
+        Self::settle_delivery(env, trade, symbol_short!("auto_done"))
+    }
+
+    /// Keeper sweep: inspect up to `limit` delivered trades from position `cursor`
+    /// of the delivered index and auto-complete the expired ones. Returns the
+    /// number settled and the cursor for the next call, 0 once the index is done.
+    pub fn settle_expired(env: Env, cursor: u32, limit: u32) -> Result<(u32, u32), P2PError> {
+        let delivered = Self::load_index(&env, &P2PDataKey::DeliveredTrades);
+
+        let now = env.ledger().timestamp();
+        let end = delivered.len().min(cursor.saturating_add(limit));
+        let mut settled: u32 = 0;
+
+        for i in cursor..end {
+            let trade: Trade = Self::load_trade(&env, delivered.get(i).unwrap())?;
+
+            if trade.status == TradeStatus::Delivered && now > trade.auto_complete_at {
+                Self::settle_delivery(env.clone(), trade, symbol_short!("auto_done"))?;
+                settled += 1;
+            }
+        }
+
+        // Settled trades leave the index, pulling the unscanned ones forward
+        let next_cursor = end - settled;
+        let next_cursor = if next_cursor < delivered.len() - settled { next_cursor } else { 0 };
+
+        Ok((settled, next_cursor))
+    }
+
+    /// Buyer opens a dispute - funds stay in escrow until a ruling
+    pub fn dispute_trade(
+        env: Env,
+        buyer: Address,
+        trade_id: u64,
+        reason: String,
+    ) -> Result<(), P2PError> {
+        buyer.require_auth();
+
+        let mut trade: Trade = Self::load_trade(&env, trade_id)?;
+
+        if trade.buyer != buyer {
+            return Err(P2PError::NotTradeBuyer);
+        }
+
+        // Can dispute a delivery during the inspection window, or non-delivery after it
+        let now = env.ledger().timestamp();
+        let can_dispute = (trade.status == TradeStatus::Delivered && now <= trade.auto_complete_at) ||
+                         (trade.status == TradeStatus::Funded && now > trade.auto_complete_at);
+
+        if !can_dispute {
+            return Err(P2PError::CannotDispute);
+        }
+
+        // Update trade - vendor now has DISP_WIN to respond
+        trade.status = TradeStatus::DisputedBuyer;
+        trade.dispute_reason = reason;
+        trade.disputed_at = now;
+        Self::save_trade(&env, &trade);
+
+        Self::remove_delivered(&env, trade_id);
+
+        let mut buyer_stats = Self::load_buyer_stats(&env, buyer.clone());
+        buyer_stats.disputes_opened += 1;
+        Self::save_buyer_stats(&env, &mut buyer_stats);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("disputed"), buyer, trade.vendor.clone()),
+            trade_id
+        );
+
+        Ok(())
+    }
+
+    /// Vendor counters a buyer dispute with evidence within the dispute window
+    pub fn counter_dispute(
+        env: Env,
+        vendor: Address,
+        trade_id: u64,
+        evidence: String,
+    ) -> Result<(), P2PError> {
+        vendor.require_auth();
+
+        let mut trade: Trade = Self::load_trade(&env, trade_id)?;
+
+        if trade.vendor != vendor {
+            return Err(P2PError::NotTradeVendor);
+        }
+
+        if trade.status != TradeStatus::DisputedBuyer {
+            return Err(P2PError::InvalidTradeState);
+        }
+
+        let dispute_window: u64 = env.storage().instance().get(&DISP_WIN).ok_or(P2PError::NotInitialized)?;
+        if env.ledger().timestamp() > trade.disputed_at + dispute_window {
+            return Err(P2PError::DisputeWindowClosed);
+        }
+
+        trade.status = TradeStatus::DisputedVendor;
+        trade.vendor_evidence = evidence;
+        Self::save_trade(&env, &trade);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("dsp_vndr"), vendor, trade.buyer.clone()),
+            trade_id
+        );
+
+        Ok(())
+    }
+
+    /// Arbiter rules on a dispute, splitting payment and slashing collateral
+    pub fn resolve_dispute(
+        env: Env,
+        arbiter: Address,
+        trade_id: u64,
+        buyer_share_bps: u32,       // Share of payment refunded to buyer
+        collateral_slash_bps: u32,  // Share of reserved collateral paid to buyer
+    ) -> Result<(), P2PError> {
+        arbiter.require_auth();
+
+        let is_arbiter: bool = env.storage().instance()
+            .get(&P2PDataKey::Arbiter(arbiter.clone()))
+            .unwrap_or(false);
+
+        if !is_arbiter {
+            return Err(P2PError::NotArbiter);
+        }
+
+        if buyer_share_bps > BPS_DENOM || collateral_slash_bps > BPS_DENOM {
+            return Err(P2PError::InvalidArgument);
+        }
+
+        let trade: Trade = Self::load_trade(&env, trade_id)?;
+
+        if trade.status != TradeStatus::DisputedBuyer && trade.status != TradeStatus::DisputedVendor {
+            return Err(P2PError::InvalidTradeState);
+        }
+
+        Self::settle_dispute(env, trade, buyer_share_bps, collateral_slash_bps, TradeOutcome::Fraud)
+    }
+
+    /// Vendor discloses a committed proof during a dispute; the hash is checked
+    /// on-chain and the result recorded either way
+    pub fn reveal_proof(
+        env: Env,
+        vendor: Address,
+        trade_id: u64,
+        proof: Bytes,
+        salt: BytesN<32>,
+    ) -> Result<bool, P2PError> {
+        vendor.require_auth();
+
+        let mut trade: Trade = Self::load_trade(&env, trade_id)?;
+
+        if trade.vendor != vendor {
+            return Err(P2PError::NotTradeVendor);
+        }
+
+        if trade.status != TradeStatus::DisputedBuyer && trade.status != TradeStatus::DisputedVendor {
+            return Err(P2PError::InvalidTradeState);
+        }
+
+        let commitment = trade.proof_commitment.clone().ok_or(P2PError::NoProofCommitment)?;
+
+        if trade.proof_matches.is_some() {
+            return Err(P2PError::ProofAlreadyRevealed);
+        }
+
+        let dispute_window: u64 = env.storage().instance().get(&DISP_WIN).ok_or(P2PError::NotInitialized)?;
+        if env.ledger().timestamp() > trade.disputed_at + dispute_window {
+            return Err(P2PError::DisputeWindowClosed);
+        }
+
+        let mut preimage = proof.clone();
+        preimage.extend_from_array(&salt.to_array());
+        let matches = env.crypto().sha256(&preimage).to_bytes() == commitment;
+
+        trade.revealed_proof = proof;
+        trade.proof_matches = Some(matches);
+        // A matching reveal answers the dispute, so it goes to the arbiter like a counter
+        if matches {
+            trade.status = TradeStatus::DisputedVendor;
+        }
+        Self::save_trade(&env, &trade);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("revealed"), vendor, trade.buyer.clone()),
+            (trade_id, matches)
+        );
+
+        Ok(matches)
+    }
+
+    /// Default ruling for the buyer once the dispute window lapses without the
+    /// vendor answering, or without a matching reveal of a committed proof
+    pub fn claim_default_ruling(env: Env, trade_id: u64) -> Result<(), P2PError> {
+        let trade: Trade = Self::load_trade(&env, trade_id)?;
+
+        let unproven = trade.status == TradeStatus::DisputedVendor
+            && trade.proof_commitment.is_some()
+            && trade.proof_matches != Some(true);
+
+        if trade.status != TradeStatus::DisputedBuyer && !unproven {
+            return Err(P2PError::InvalidTradeState);
+        }
+
+        let dispute_window: u64 = env.storage().instance().get(&DISP_WIN).ok_or(P2PError::NotInitialized)?;
+        if env.ledger().timestamp() <= trade.disputed_at + dispute_window {
+            return Err(P2PError::DisputeWindowOpen);
+        }
+
+        Self::settle_dispute(env, trade, BPS_DENOM, BPS_DENOM, TradeOutcome::Defaulted)
+    }
+
+    /// Ask to back out of a trade; the other party must approve.
+    /// A buyer whose vendor missed the delivery deadline cancels immediately.
+    pub fn request_cancel(env: Env, caller: Address, trade_id: u64) -> Result<(), P2PError> {
+        caller.require_auth();
+
+        let mut trade: Trade = Self::load_trade(&env, trade_id)?;
+
+        if caller != trade.buyer && caller != trade.vendor {
+            return Err(P2PError::NotTradeParty);
+        }
+
+        if trade.status != TradeStatus::Funded && trade.status != TradeStatus::Delivered {
+            return Err(P2PError::InvalidTradeState);
+        }
+
+        let delivery_overdue = trade.status == TradeStatus::Funded
+            && env.ledger().timestamp() > trade.auto_complete_at;
+
+        if caller == trade.buyer && delivery_overdue {
+            return Self::cancel_trade(env, trade);
+        }
+
+        trade.cancel_requested_by = Some(caller.clone());
+        Self::save_trade(&env, &trade);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("cncl_req"), caller),
+            trade_id
+        );
+
+        Ok(())
+    }
+
+    /// Counterparty approves a pending cancel request
+    pub fn approve_cancel(env: Env, caller: Address, trade_id: u64) -> Result<(), P2PError> {
+        caller.require_auth();
+
+        let trade: Trade = Self::load_trade(&env, trade_id)?;
+
+        if caller != trade.buyer && caller != trade.vendor {
+            return Err(P2PError::NotTradeParty);
+        }
+
+        if trade.status != TradeStatus::Funded && trade.status != TradeStatus::Delivered {
+            return Err(P2PError::InvalidTradeState);
+        }
+
+        match &trade.cancel_requested_by {
+            Some(requester) if *requester != caller => {}
+            Some(_) => return Err(P2PError::CannotApproveOwnRequest),
+            None => return Err(P2PError::NoCancelRequested),
+        }
+
+        Self::cancel_trade(env, trade)
+    }
+
+    /// Vendor turns down a funded trade; buyer is refunded
+    pub fn decline_trade(env: Env, vendor: Address, trade_id: u64) -> Result<(), P2PError> {
+        vendor.require_auth();
+
+        let trade: Trade = Self::load_trade(&env, trade_id)?;
+
+        if trade.vendor != vendor {
+            return Err(P2PError::NotTradeVendor);
+        }
+
+        if trade.status != TradeStatus::Funded {
+            return Err(P2PError::InvalidTradeState);
+        }
+
+        let value = Self::reputation_value(&env, &trade);
+        Self::record_vendor_decline(&env, vendor, value);
+        Self::cancel_trade(env, trade)
+    }
+
+    /// Buyer claims the part of an arbiter ruling that the escrow and slashed
+    /// collateral didn't cover. Pays what the fund holds; the rest stays claimable.
+    pub fn claim_insurance(env: Env, buyer: Address, trade_id: u64) -> Result<i128, P2PError> {
+        buyer.require_auth();
+
+        let mut trade: Trade = Self::load_trade(&env, trade_id)?;
+
+        if trade.buyer != buyer {
+            return Err(P2PError::NotTradeBuyer);
+        }
+
+        if trade.status != TradeStatus::Resolved {
+            return Err(P2PError::InvalidTradeState);
+        }
+
+        let key = P2PDataKey::InsuranceBalance(trade.payment_token.clone());
+        let balance: i128 = env.storage().instance().get(&key).unwrap_or(0);
+        let payout = (trade.insured_loss - trade.insurance_paid).min(balance);
+
+        if payout <= 0 {
+            return Err(P2PError::NothingToWithdraw);
+        }
+
+        env.storage().instance().set(&key, &(balance - payout));
+        trade.insurance_paid += payout;
+        Self::save_trade(&env, &trade);
+
+        Self::transfer_tokens(env.clone(), &trade.payment_token, &buyer, &payout);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("ins_claim"), buyer),
+            (trade_id, payout, balance - payout)
+        );
+
+        Ok(payout)
+    }
+
+    // ========================================================================
+    // BUYER REQUESTS
+    // ========================================================================
+
+    /// Post a request for quotes, escrowing the buyer's maximum budget
+    pub fn create_request(
+        env: Env,
+        buyer: Address,
+        category: Category,
+        title: String,
+        description: String,
+        payment_token: Address,
+        budget: i128,
+    ) -> Result<u64, P2PError> {
+        buyer.require_auth();
+
+        if budget <= 0 {
+            return Err(P2PError::InvalidAmount);
+        }
+
+        if !Self::is_supported(&env, &payment_token) {
+            return Err(P2PError::TokenNotSupported);
+        }
+
+        Self::transfer_from(
+            env.clone(),
+            &payment_token,
+            &buyer,
+            &env.current_contract_address(),
+            &budget,
+        );
+
+        let mut request_count: u64 = env.storage().instance().get(&REQ_CNT).unwrap_or(0);
+        request_count += 1;
+
+        let request = BuyerRequest {
+            id: request_count,
+            buyer: buyer.clone(),
+            category: category.clone(),
+            title,
+            description,
+            payment_token,
+            budget,
+            status: RequestStatus::Open,
+            quote_count: 0,
+            trade_id: 0,
+            created_at: env.ledger().timestamp(),
+            updated_at: env.ledger().timestamp(),
+        };
+
+        Self::save_request(&env, &request);
+        env.storage().instance().set(&REQ_CNT, &request_count);
+
+        Self::add_to_index(&env, &P2PDataKey::BuyerRequests(buyer.clone()), request_count);
+        Self::add_to_index(&env, &P2PDataKey::CategoryRequests(category), request_count);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("req_new"), buyer),
+            (request_count, budget)
+        );
+
+        Ok(request_count)
+    }
+
+    /// Withdraw an open request: refund the budget and release every quote's collateral
+    pub fn cancel_request(env: Env, buyer: Address, request_id: u64) -> Result<(), P2PError> {
+        buyer.require_auth();
+
+        let mut request = Self::load_request(&env, request_id)?;
+
+        if request.buyer != buyer {
+            return Err(P2PError::NotRequestOwner);
+        }
+
+        if request.status != RequestStatus::Open {
+            return Err(P2PError::InvalidOfferState);
+        }
+
+        Self::transfer_tokens(env.clone(), &request.payment_token, &buyer, &request.budget);
+        Self::reject_quotes(&env, &request, 0);
+
+        request.status = RequestStatus::Cancelled;
+        request.updated_at = env.ledger().timestamp();
+        Self::save_request(&env, &request);
+
+        Self::remove_from_index(&env, &P2PDataKey::CategoryRequests(request.category.clone()), request_id);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("req_cnl"), buyer),
+            request_id
+        );
+
+        Ok(())
+    }
+
+    /// Vendor quotes a total price for a request, locking collateral in the
+    /// request's payment token as they would for an offer
+    pub fn submit_quote(
+        env: Env,
+        vendor: Address,
+        request_id: u64,
+        price: i128,
+        collateral_amount: i128,
+        note: String,
+    ) -> Result<u64, P2PError> {
+        vendor.require_auth();
+
+        let mut request = Self::load_request(&env, request_id)?;
+
+        if request.status != RequestStatus::Open {
+            return Err(P2PError::InvalidOfferState);
+        }
+
+        if request.buyer == vendor {
+            return Err(P2PError::SelfTrade);
+        }
+
+        if price <= 0 || price > request.budget {
+            return Err(P2PError::InvalidAmount);
+        }
+
+        if request.quote_count >= MAX_QUOTES || note.len() > MAX_COMMENT_LEN {
+            return Err(P2PError::InvalidArgument);
+        }
+
+        Self::check_trade_limits(&env, &request.payment_token, price)?;
+
+        if collateral_amount < Self::required_collateral(&env, &request.payment_token, price)? {
+            return Err(P2PError::InsufficientCollateral);
+        }
+
+        Self::transfer_from(
+            env.clone(),
+            &request.payment_token,
+            &vendor,
+            &env.current_contract_address(),
+            &collateral_amount,
+        );
+
+        let mut quote_count: u64 = env.storage().instance().get(&QUOTE_CNT).unwrap_or(0);
+        quote_count += 1;
+
+        let quote = Quote {
+            id: quote_count,
+            request_id,
+            vendor: vendor.clone(),
+            price,
+            collateral_amount,
+            note,
+            status: QuoteStatus::Pending,
+            offer_id: 0,
+            created_at: env.ledger().timestamp(),
+        };
+
+        Self::save_quote(&env, &quote);
+        env.storage().instance().set(&QUOTE_CNT, &quote_count);
+
+        request.quote_count += 1;
+        request.updated_at = env.ledger().timestamp();
+        Self::save_request(&env, &request);
+
+        Self::add_to_index(&env, &P2PDataKey::RequestQuotes(request_id), quote_count);
+        Self::add_to_index(&env, &P2PDataKey::VendorQuotes(vendor.clone()), quote_count);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("quote_new"), vendor, request.buyer),
+            (request_id, quote_count, price)
+        );
+
+        Ok(quote_count)
+    }
+
+    /// Vendor pulls a pending quote and gets its collateral back
+    pub fn withdraw_quote(env: Env, vendor: Address, quote_id: u64) -> Result<(), P2PError> {
+        vendor.require_auth();
+
+        let mut quote = Self::load_quote(&env, quote_id)?;
+
+        if quote.vendor != vendor {
+            return Err(P2PError::NotQuoteOwner);
+        }
+
+        if quote.status != QuoteStatus::Pending {
+            return Err(P2PError::InvalidOfferState);
+        }
+
+        let request = Self::load_request(&env, quote.request_id)?;
+        Self::transfer_tokens(env.clone(), &request.payment_token, &vendor, &quote.collateral_amount);
+
+        quote.status = QuoteStatus::Withdrawn;
+        Self::save_quote(&env, &quote);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("quote_wd"), vendor),
+            (quote.request_id, quote_id)
+        );
+
+        Ok(())
+    }
+
+    /// Buyer accepts a quote. It becomes an unlisted single-unit offer with a
+    /// normal funded trade against it; the unused budget is refunded and every
+    /// other quote's collateral is released. The vendor reclaims the offer's
+    /// collateral with cancel_offer once the trade settles.
+    pub fn accept_quote(
+        env: Env,
+        buyer: Address,
+        quote_id: u64,
+        buyer_key: Option<BytesN<32>>,
+        hashlock: Option<BytesN<32>>,
+    ) -> Result<u64, P2PError> {
+        buyer.require_auth();
+
+        let mut quote = Self::load_quote(&env, quote_id)?;
+        let mut request = Self::load_request(&env, quote.request_id)?;
+
+        if request.buyer != buyer {
+            return Err(P2PError::NotRequestOwner);
+        }
+
+        if request.status != RequestStatus::Open || quote.status != QuoteStatus::Pending {
+            return Err(P2PError::InvalidOfferState);
+        }
+
+        if hashlock.is_some() != (request.category == Category::CrossChain) {
+            return Err(P2PError::InvalidArgument);
+        }
+
+        // The collateral floor may have risen since the quote was locked
+        let reservation = Self::required_collateral(&env, &request.payment_token, quote.price)?;
+        if reservation > quote.collateral_amount {
+            return Err(P2PError::InsufficientFreeCollateral);
+        }
+
+        let mut offer_count: u64 = env.storage().instance().get(&OFFER_CNT).unwrap_or(0);
+        offer_count += 1;
+
+        let mut offer = Offer {
+            id: offer_count,
+            vendor: quote.vendor.clone(),
+            category: request.category.clone(),
+            title: request.title.clone(),
+            description: request.description.clone(),
+            price: quote.price,
+            payment_token: request.payment_token.clone(),
+            quantity_total: 1,
+            quantity_remaining: 1,
+            collateral: CollateralInfo {
+                amount: quote.collateral_amount,
+                reserved: 0,
+                token_symbol: Self::load_token_info(&env, &request.payment_token)?.symbol,
+                token_address: request.payment_token.clone(),
+                locked_at: env.ledger().timestamp(),
+                yield_accrued: 0,
+                supplied: 0,
+                pool_shares: 0,
+            },
+            status: OfferStatus::Active,
+            active_trade_count: 0,
+            completed_trade_count: 0,
+            min_buyer_score: 0,
+            require_clean_buyer: false,
+            milestones: Vec::new(&env),
+            request_id: request.id,
+            expires_at: 0,
+            ref_price: 0,
+            accepted_tokens: Vec::new(&env),
+            payment_options: Vec::new(&env),
+            created_at: env.ledger().timestamp(),
+            updated_at: env.ledger().timestamp(),
+        };
+        env.storage().instance().set(&OFFER_CNT, &offer_count);
+        Self::add_to_index(&env, &P2PDataKey::VendorOffers(quote.vendor.clone()), offer_count);
+
+        // Price stays in escrow for the trade; the rest of the budget goes back
+        let unused = request.budget - quote.price;
+        if unused > 0 {
+            Self::transfer_tokens(env.clone(), &request.payment_token, &buyer, &unused);
+        }
+
+        let trade_id = Self::open_trade(
+            &env,
+            &mut offer,
+            buyer.clone(),
+            1,
+            quote.price,
+            request.payment_token.clone(),
+            reservation,
+            buyer_key,
+            hashlock,
+        )?;
+
+        quote.status = QuoteStatus::Accepted;
+        quote.offer_id = offer_count;
+        Self::save_quote(&env, &quote);
+        Self::reject_quotes(&env, &request, quote_id);
+
+        request.status = RequestStatus::Filled;
+        request.trade_id = trade_id;
+        request.updated_at = env.ledger().timestamp();
+        Self::save_request(&env, &request);
+
+        Self::remove_from_index(&env, &P2PDataKey::CategoryRequests(request.category.clone()), request.id);
+
+        env.events().publish(
+            (symbol_short!("quote_acc"), buyer, quote.vendor),
+            (request.id, quote_id, trade_id)
+        );
+
+        Ok(trade_id)
+    }
+
+    // ========================================================================
+    // REVIEWS
+    // ========================================================================
+
+    /// Rate the other party of a settled trade; each side may rate once
+    pub fn rate_trade(
+        env: Env,
+        rater: Address,
+        trade_id: u64,
+        stars: u32,
+        comment: String,
+    ) -> Result<(), P2PError> {
+        rater.require_auth();
+
+        let trade: Trade = Self::load_trade(&env, trade_id)?;
+
+        let ratee = if rater == trade.buyer {
+            trade.vendor.clone()
+        } else if rater == trade.vendor {
+            trade.buyer.clone()
+        } else {
+            return Err(P2PError::NotTradeParty);
+        };
+
+        if trade.status != TradeStatus::Completed && trade.status != TradeStatus::Resolved {
+            return Err(P2PError::InvalidTradeState);
+        }
+
+        if stars == 0 || stars > MAX_STARS {
+            return Err(P2PError::InvalidArgument);
+        }
+
+        if comment.len() > MAX_COMMENT_LEN {
+            return Err(P2PError::InvalidArgument);
+        }
+
+        let key = P2PDataKey::Review(trade_id, rater.clone());
+        if Self::read_entry::<Review>(&env, &key).is_some() {
+            return Err(P2PError::AlreadyRated);
+        }
+
+        let review = Review {
+            trade_id,
+            rater: rater.clone(),
+            ratee: ratee.clone(),
+            stars,
+            comment,
+            created_at: env.ledger().timestamp(),
+        };
+        Self::write_entry(&env, &key, &review);
+
+        if rater == trade.buyer {
+            Self::add_to_index(&env, &P2PDataKey::VendorReviews(ratee.clone()), trade_id);
+            let value = Self::reputation_value(&env, &trade);
+            Self::record_vendor_rating(&env, ratee.clone(), value, stars);
+        } else {
+            let mut buyer_stats = Self::load_buyer_stats(&env, ratee.clone());
+            buyer_stats.rating_average = Self::rolling_average(
+                buyer_stats.rating_average,
+                buyer_stats.rating_count,
+                stars,
+            );
+            buyer_stats.rating_count += 1;
+            Self::save_buyer_stats(&env, &mut buyer_stats);
+        }
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("rated"), rater, ratee),
+            (trade_id, stars)
+        );
+
+        Ok(())
+    }
+
+    /// Buyer reviews of a vendor, oldest first, starting at position `cursor`
+    pub fn get_vendor_reviews(env: Env, vendor: Address, cursor: u64, limit: u32) -> Result<ReviewPage, P2PError> {
+        if limit == 0 {
+            return Err(P2PError::InvalidArgument);
+        }
+
+        let trade_ids = Self::load_index(&env, &P2PDataKey::VendorReviews(vendor));
+        let total = trade_ids.len() as u64;
+        let end = total.min(cursor + limit.min(MAX_PAGE_SIZE) as u64);
+
+        let mut reviews: Vec<Review> = Vec::new(&env);
+        for i in cursor..end {
+            let trade_id = trade_ids.get(i as u32).unwrap();
+            let trade: Trade = Self::load_trade(&env, trade_id)?;
+            if let Some(review) = Self::read_entry(&env, &P2PDataKey::Review(trade_id, trade.buyer)) {
+                reviews.push_back(review);
+            }
+        }
+
+        let next_cursor = if end < total { end } else { 0 };
+
+        Ok(ReviewPage { reviews, next_cursor })
+    }
+
+    // ========================================================================
+    // STORAGE MAINTENANCE
+    // ========================================================================
+
+    /// Keeper hook: extend an offer (and its trade index) before it gets archived
+    pub fn bump_offer(env: Env, offer_id: u64) -> Result<(), P2PError> {
+        let offer: Offer = Self::load_offer(&env, offer_id)?;
+        Self::save_offer(&env, &offer);
+
+        let trades_key = P2PDataKey::OfferTrades(offer_id);
+        if env.storage().persistent().has(&trades_key) {
+            Self::extend_entry(&env, &trades_key);
+        }
+
+        Ok(())
+    }
+
+    /// Keeper hook: extend a trade record before it gets archived
+    pub fn bump_trade(env: Env, trade_id: u64) -> Result<(), P2PError> {
+        let trade: Trade = Self::load_trade(&env, trade_id)?;
+        Self::save_trade(&env, &trade);
+        Ok(())
+    }
+
+    /// Replace the contract's code with an uploaded WASM. Deployments upgraded
+    /// from the original build then run migrate_storage.
+    pub fn upgrade(env: Env, admin: Address, new_wasm_hash: BytesN<32>) -> Result<(), P2PError> {
+        admin.require_auth();
+        Self::require_admin(&env, &admin)?;
+
+        env.deployer().update_current_contract_wasm(new_wasm_hash.clone());
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish((symbol_short!("upgraded"), admin), new_wasm_hash);
+
+        Ok(())
+    }
+
+    /// Move up to `limit` offers/trades (and their indexes) from the original
+    /// build's instance storage into persistent storage, converting each record
+    /// to the current layout. Resumes where the last call stopped and returns
+    /// how many records were processed; 0 means migration is complete.
+    pub fn migrate_storage(env: Env, admin: Address, limit: u32) -> Result<u32, P2PError> {
+        admin.require_auth();
+        Self::require_admin(&env, &admin)?;
+
+        Self::migrate_entry(&env, &P2PDataKey::ActiveOffers);
+        Self::migrate_entry(&env, &P2PDataKey::DeliveredTrades);
+
+        let offer_count: u64 = env.storage().instance().get(&OFFER_CNT).unwrap_or(0);
+        let trade_count: u64 = env.storage().instance().get(&TRADE_CNT).unwrap_or(0);
+        let mut cursor: u64 = env.storage().instance().get(&MIG_CUR).unwrap_or(0);
+        let mut processed: u32 = 0;
+
+        while processed < limit && cursor < offer_count + trade_count {
+            cursor += 1;
+            processed += 1;
+
+            if cursor <= offer_count {
+                Self::migrate_offer(&env, cursor);
+                Self::migrate_entry(&env, &P2PDataKey::OfferTrades(cursor));
+                if let Some(offer) = Self::read_entry::<Offer>(&env, &P2PDataKey::Offer(cursor)) {
+                    Self::migrate_entry(&env, &P2PDataKey::VendorOffers(offer.vendor.clone()));
+                    Self::migrate_vendor_stats(&env, offer.vendor);
+                    Self::migrate_entry(&env, &P2PDataKey::CategoryOffers(offer.category));
+                }
+            } else {
+                let trade_id = cursor - offer_count;
+                Self::migrate_trade(&env, trade_id);
+                if let Some(trade) = Self::read_entry::<Trade>(&env, &P2PDataKey::Trade(trade_id)) {
+                    Self::migrate_entry(&env, &P2PDataKey::BuyerTrades(trade.buyer));
+                    Self::migrate_entry(&env, &P2PDataKey::VendorTrades(trade.vendor));
+                }
+            }
+        }
+
+        env.storage().instance().set(&MIG_CUR, &cursor);
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("migrated"), admin),
+            (processed, cursor)
+        );
+
+        Ok(processed)
+    }
+
+    // ========================================================================
+    // QUERY FUNCTIONS
+    // ========================================================================
+
+    pub fn get_offer(env: Env, offer_id: u64) -> Result<Offer, P2PError> {
+        Self::load_offer(&env, offer_id)
+    }
+
+    pub fn get_trade(env: Env, trade_id: u64) -> Result<Trade, P2PError> {
+        Self::load_trade(&env, trade_id)
+    }
+
+    /// Current fee in basis points and treasury address
+    pub fn get_fee_config(env: Env) -> Result<(u32, Address), P2PError> {
+        let fee_bps: u32 = env.storage().instance().get(&FEE_BPS).unwrap_or(0);
+        let treasury: Address = env.storage().instance()
+            .get(&TREASURY).ok_or(P2PError::NotInitialized)?;
+        Ok((fee_bps, treasury))
+    }
+
+    /// Current value of an offer's pool position, interest included
+    pub fn get_supplied_collateral(env: Env, offer_id: u64) -> Result<i128, P2PError> {
+        let offer: Offer = Self::load_offer(&env, offer_id)?;
+        if offer.collateral.pool_shares == 0 {
+            return Ok(0);
+        }
+
+        let pool_address: Address = env.storage().instance().get(&YIELD_POOL).ok_or(P2PError::PoolNotSet)?;
+        let token = offer.collateral.token_address.clone();
+        let total_shares: i128 = env.storage().instance()
+            .get(&P2PDataKey::PoolShares(token.clone()))
+            .unwrap_or(0);
+        let position = YieldPoolClient::new(&env, &pool_address).balance(&env.current_contract_address(), &token);
+        Ok(offer.collateral.pool_shares * position / total_shares)
+    }
+
+    /// Registered tokens with their metadata and limits
+    pub fn get_supported_tokens(env: Env) -> Vec<TokenInfo> {
+        let mut infos: Vec<TokenInfo> = Vec::new(&env);
+        for token in Self::supported_tokens(&env).iter() {
+            if let Some(info) = Self::token_info(&env, &token) {
+                infos.push_back(info);
+            }
+        }
+        infos
+    }
+
+    pub fn get_token_info(env: Env, token: Address) -> Result<TokenInfo, P2PError> {
+        Self::load_token_info(&env, &token)
+    }
+
+    /// Insurance fund balance for a token
+    pub fn get_insurance_balance(env: Env, token: Address) -> i128 {
+        env.storage().instance()
+            .get(&P2PDataKey::InsuranceBalance(token))
+            .unwrap_or(0)
+    }
+
+    /// Shares of slashes and fees routed to insurance, in basis points
+    pub fn get_insurance_config(env: Env) -> (u32, u32) {
+        let slash_share: u32 = env.storage().instance().get(&INS_SLASH).unwrap_or(0);
+        let fee_share: u32 = env.storage().instance().get(&INS_FEE).unwrap_or(0);
+        (slash_share, fee_share)
+    }
+
+    /// Fees collected and not yet withdrawn for a token
+    pub fn get_fee_balance(env: Env, token: Address) -> i128 {
+        env.storage().instance()
+            .get(&P2PDataKey::FeeBalance(token))
+            .unwrap_or(0)
+    }
+
+    /// Collateral not reserved by open trades
+    pub fn get_free_collateral(env: Env, offer_id: u64) -> Result<i128, P2PError> {
+        let offer: Offer = Self::load_offer(&env, offer_id)?;
+        Ok(Self::free_collateral(&offer))
+    }
+
+    /// Page through offers matching `filter`, in ID order after `cursor` (0 to start).
+    /// Each call inspects at most MAX_PAGE_SCAN IDs, so a page may come back short
+    /// with a non-zero next_cursor.
+    pub fn list_offers(env: Env, filter: OfferFilter, cursor: u64, limit: u32) -> Result<OfferPage, P2PError> {
+        if limit == 0 {
+            return Err(P2PError::InvalidArgument);
+        }
+        let limit = limit.min(MAX_PAGE_SIZE);
+
+        let offer_count: u64 = env.storage().instance().get(&OFFER_CNT).unwrap_or(0);
+        let scan_end = offer_count.min(cursor + MAX_PAGE_SCAN as u64);
+
+        let mut offers: Vec<Offer> = Vec::new(&env);
+        let mut last = cursor;
+
+        while last < scan_end && offers.len() < limit {
+            last += 1;
+            let offer: Offer = Self::load_offer(&env, last)?;
+            if Self::offer_matches(&env, &offer, &filter) {
+                offers.push_back(offer);
+            }
+        }
+
+        let next_cursor = if last < offer_count { last } else { 0 };
+
+        Ok(OfferPage { offers, next_cursor })
+    }
+
+    pub fn get_vendor_offers(env: Env, vendor: Address) -> Vec<u64> {
+        Self::load_index(&env, &P2PDataKey::VendorOffers(vendor))
+    }
+
+    /// Listed offers in a category, minus any past their expiry
+    pub fn get_category_offers(env: Env, category: Category) -> Vec<u64> {
+        Self::unexpired(&env, Self::load_index(&env, &P2PDataKey::CategoryOffers(category)))
+    }
+
+    /// Listed offers, minus any past their expiry
+    pub fn get_active_offers(env: Env) -> Vec<u64> {
+        Self::unexpired(&env, Self::load_index(&env, &P2PDataKey::ActiveOffers))
+    }
+
+    pub fn get_request(env: Env, request_id: u64) -> Result<BuyerRequest, P2PError> {
+        Self::load_request(&env, request_id)
+    }
+
+    pub fn get_quote(env: Env, quote_id: u64) -> Result<Quote, P2PError> {
+        Self::load_quote(&env, quote_id)
+    }
+
+    /// Open requests in a category
+    pub fn get_category_requests(env: Env, category: Category) -> Vec<u64> {
+        Self::load_index(&env, &P2PDataKey::CategoryRequests(category))
+    }
+
+    pub fn get_buyer_requests(env: Env, buyer: Address) -> Vec<u64> {
+        Self::load_index(&env, &P2PDataKey::BuyerRequests(buyer))
+    }
+
+    pub fn get_request_quotes(env: Env, request_id: u64) -> Vec<u64> {
+        Self::load_index(&env, &P2PDataKey::RequestQuotes(request_id))
+    }
+
+    pub fn get_vendor_quotes(env: Env, vendor: Address) -> Vec<u64> {
+        Self::load_index(&env, &P2PDataKey::VendorQuotes(vendor))
+    }
+
+    pub fn get_buyer_trades(env: Env, buyer: Address) -> Vec<u64> {
+        Self::load_index(&env, &P2PDataKey::BuyerTrades(buyer))
+    }
+
+    pub fn get_vendor_trades(env: Env, vendor: Address) -> Vec<u64> {
+        Self::load_index(&env, &P2PDataKey::VendorTrades(vendor))
+    }
+
+    pub fn get_vendor_stats(env: Env, vendor: Address) -> VendorStats {
+        Self::load_vendor_stats(&env, vendor)
+    }
+
+    /// Vendor reputation with its inputs, decayed to the current ledger time
+    pub fn get_reputation(env: Env, vendor: Address) -> ReputationBreakdown {
+        let mut stats = Self::load_vendor_stats(&env, vendor);
+        Self::decay_vendor_stats(&env, &mut stats);
+        Self::reputation_breakdown(&env, &stats)
+    }
+
+    pub fn get_buyer_stats(env: Env, buyer: Address) -> BuyerStats {
+        Self::load_buyer_stats(&env, buyer)
+    }
+
+    // ========================================================================
+    // INTERNAL HELPERS
+    // ========================================================================
+
+    /// Caller must be the stored admin
+    fn require_admin(env: &Env, admin: &Address) -> Result<(), P2PError> {
+        let stored_admin: Address = env.storage().instance()
+            .get(&ADMIN).ok_or(P2PError::NotInitialized)?;
+
+        if *admin != stored_admin {
+            return Err(P2PError::NotAdmin);
+        }
+
+        Ok(())
+    }
+
+    fn load_offer(env: &Env, offer_id: u64) -> Result<Offer, P2PError> {
+        Self::read_entry(env, &P2PDataKey::Offer(offer_id))
+            .ok_or(P2PError::OfferNotFound)
+    }
+
+    fn load_trade(env: &Env, trade_id: u64) -> Result<Trade, P2PError> {
+        Self::read_entry(env, &P2PDataKey::Trade(trade_id))
+            .ok_or(P2PError::TradeNotFound)
+    }
+
+    fn save_offer(env: &Env, offer: &Offer) {
+        Self::write_entry(env, &P2PDataKey::Offer(offer.id), offer);
+    }
+
+    fn save_trade(env: &Env, trade: &Trade) {
+        Self::write_entry(env, &P2PDataKey::Trade(trade.id), trade);
+    }
+
+    fn load_request(env: &Env, request_id: u64) -> Result<BuyerRequest, P2PError> {
+        Self::read_entry(env, &P2PDataKey::Request(request_id))
+            .ok_or(P2PError::RequestNotFound)
+    }
+
+    fn load_quote(env: &Env, quote_id: u64) -> Result<Quote, P2PError> {
+        Self::read_entry(env, &P2PDataKey::Quote(quote_id))
+            .ok_or(P2PError::QuoteNotFound)
+    }
+
+    fn save_request(env: &Env, request: &BuyerRequest) {
+        Self::write_entry(env, &P2PDataKey::Request(request.id), request);
+    }
+
+    fn save_quote(env: &Env, quote: &Quote) {
+        Self::write_entry(env, &P2PDataKey::Quote(quote.id), quote);
+    }
+
+    /// Reject a request's pending quotes other than `keep`, returning their collateral
+    fn reject_quotes(env: &Env, request: &BuyerRequest, keep: u64) {
+        let quote_ids = Self::load_index(env, &P2PDataKey::RequestQuotes(request.id));
+        for quote_id in quote_ids.iter() {
+            if quote_id == keep {
+                continue;
+            }
+            if let Some(mut quote) = Self::read_entry::<Quote>(env, &P2PDataKey::Quote(quote_id)) {
+                if quote.status == QuoteStatus::Pending {
+                    Self::transfer_tokens(env.clone(), &request.payment_token, &quote.vendor, &quote.collateral_amount);
+                    quote.status = QuoteStatus::Rejected;
+                    Self::save_quote(env, &quote);
+                }
+            }
+        }
+    }
+
+    fn load_index(env: &Env, key: &P2PDataKey) -> Vec<u64> {
+        Self::read_entry(env, key).unwrap_or(Vec::new(env))
+    }
+
+    /// Read a persistent entry, falling back to pre-migration instance storage
+    fn read_entry<V: TryFromVal<Env, Val>>(env: &Env, key: &P2PDataKey) -> Option<V> {
+        env.storage().persistent()
+            .get(key)
+            .or_else(|| env.storage().instance().get(key))
+    }
+
+    /// Write a persistent entry and extend its TTL per the key's policy
+    fn write_entry<V: IntoVal<Env, Val>>(env: &Env, key: &P2PDataKey, value: &V) {
+        env.storage().persistent().set(key, value);
+        Self::extend_entry(env, key);
+    }
+
+    fn extend_entry(env: &Env, key: &P2PDataKey) {
+        let (threshold, extend_to) = match key {
+            P2PDataKey::Offer(_) | P2PDataKey::Request(_) | P2PDataKey::Quote(_) => {
+                (OFFER_TTL_THRESHOLD, OFFER_TTL_EXTEND)
+            }
+            P2PDataKey::Trade(_) => (TRADE_TTL_THRESHOLD, TRADE_TTL_EXTEND),
+            _ => (INDEX_TTL_THRESHOLD, INDEX_TTL_EXTEND),
+        };
+        env.storage().persistent().extend_ttl(key, threshold, extend_to);
+    }
+
+    /// Move a legacy instance entry into persistent storage (newer persistent data wins)
+    fn migrate_entry(env: &Env, key: &P2PDataKey) {
+        let legacy: Option<Val> = env.storage().instance().get(key);
+        if let Some(value) = legacy {
+            if !env.storage().persistent().has(key) {
+                Self::write_entry(env, key, &value);
+            }
+            env.storage().instance().remove(key);
+        }
+    }
+
+    /// Convert an original-layout offer. It becomes a single-unit listing; open
+    /// trades reserve their collateral as they are migrated after it.
+    fn migrate_offer(env: &Env, offer_id: u64) {
+        let key = P2PDataKey::Offer(offer_id);
+        let legacy: Option<LegacyOffer> = env.storage().instance().get(&key);
+        let Some(legacy) = legacy else { return };
+        env.storage().instance().remove(&key);
+
+        Self::migrate_token(env, &legacy.collateral.token_address);
+        Self::migrate_token(env, &legacy.payment_token);
+
+        let token_symbol = match Self::token_info(env, &legacy.collateral.token_address) {
+            Some(info) => info.symbol,
+            None => match legacy.collateral.token_type {
+                LegacyTokenType::XLM => String::from_str(env, "XLM"),
+                LegacyTokenType::USDC => String::from_str(env, "USDC"),
+            },
+        };
+
+        let (status, quantity_remaining) = match legacy.status {
+            OfferStatus::Cancelled => (OfferStatus::Cancelled, 0),
+            OfferStatus::Paused => (OfferStatus::Paused, 1),
+            _ => (OfferStatus::Active, 1),
+        };
+
+        let offer = Offer {
+            id: legacy.id,
+            vendor: legacy.vendor,
+            category: legacy.category,
+            title: legacy.title,
+            description: legacy.description,
+            price: legacy.price,
+            payment_token: legacy.payment_token,
+            quantity_total: 1,
+            quantity_remaining,
+            collateral: CollateralInfo {
+                amount: legacy.collateral.amount,
+                reserved: 0,
+                token_symbol,
+                token_address: legacy.collateral.token_address,
+                locked_at: legacy.collateral.locked_at,
+                yield_accrued: legacy.collateral.yield_accrued,
+                supplied: 0,
+                pool_shares: 0,
+            },
+            status,
+            active_trade_count: legacy.active_trade_count,
+            completed_trade_count: legacy.completed_trade_count,
+            min_buyer_score: 0,
+            require_clean_buyer: false,
+            milestones: Vec::new(env),
+            request_id: 0,
+            expires_at: 0,
+            ref_price: 0,
+            accepted_tokens: Vec::new(env),
+            payment_options: Vec::new(env),
+            created_at: legacy.created_at,
+            updated_at: legacy.updated_at,
+        };
+        Self::save_offer(env, &offer);
+    }
+
+    /// Convert an original-layout trade. The original build pointed every open
+    /// trade at the offer's whole collateral; each now reserves what is still free.
+    fn migrate_trade(env: &Env, trade_id: u64) {
+        let key = P2PDataKey::Trade(trade_id);
+        let legacy: Option<LegacyTrade> = env.storage().instance().get(&key);
+        let Some(legacy) = legacy else { return };
+        env.storage().instance().remove(&key);
+
+        let open = matches!(
+            legacy.status,
+            TradeStatus::Funded | TradeStatus::Delivered | TradeStatus::DisputedBuyer | TradeStatus::DisputedVendor
+        );
+        let mut collateral_amount = legacy.collateral_amount;
+        if open {
+            if let Ok(mut offer) = Self::load_offer(env, legacy.offer_id) {
+                collateral_amount = collateral_amount.min(Self::free_collateral(&offer)).max(0);
+                offer.collateral.reserved += collateral_amount;
+                Self::save_offer(env, &offer);
+            }
+        }
+
+        // Disputes opened before the upgrade get a full window from now
+        let disputed_at = match legacy.status {
+            TradeStatus::DisputedBuyer | TradeStatus::DisputedVendor => env.ledger().timestamp(),
+            _ => 0,
+        };
+
+        let trade = Trade {
+            id: legacy.id,
+            offer_id: legacy.offer_id,
+            buyer: legacy.buyer,
+            vendor: legacy.vendor,
+            units: 1,
+            amount: legacy.amount,
+            payment_token: legacy.payment_token,
+            collateral_amount,
+            milestones: Vec::new(env),
+            milestones_released: 0,
+            released_amount: 0,
+            released_collateral: 0,
+            status: legacy.status,
+            delivery_proof: legacy.delivery_proof,
+            buyer_key: None,
+            sealed_proof: Bytes::new(env),
+            proof_commitment: None,
+            revealed_proof: Bytes::new(env),
+            proof_matches: None,
+            insured_loss: 0,
+            insurance_paid: 0,
+            hashlock: None,
+            preimage: None,
+            created_at: legacy.created_at,
+            delivered_at: legacy.delivered_at,
+            completed_at: legacy.completed_at,
+            dispute_reason: legacy.dispute_reason,
+            vendor_evidence: String::from_str(env, ""),
+            disputed_at,
+            cancel_requested_by: None,
+            auto_complete_at: legacy.auto_complete_at,
+        };
+
+        if trade.status == TradeStatus::Delivered {
+            Self::add_to_index(env, &P2PDataKey::DeliveredTrades, trade.id);
+        }
+        Self::save_trade(env, &trade);
+    }
+
+    /// Convert original-layout vendor stats. Counts carry over; the score restarts
+    /// from the neutral prior since the old volumes weren't value-weighted.
+    fn migrate_vendor_stats(env: &Env, vendor: Address) {
+        let key = P2PDataKey::VendorStats(vendor.clone());
+        let legacy: Option<LegacyVendorStats> = env.storage().instance().get(&key);
+        let Some(legacy) = legacy else { return };
+        env.storage().instance().remove(&key);
+
+        let mut stats = Self::load_vendor_stats(env, vendor);
+        stats.total_trades = legacy.total_trades;
+        stats.completed_trades = legacy.completed_trades;
+        stats.disputed_trades = legacy.disputed_trades;
+        stats.total_volume = legacy.total_volume;
+        Self::write_entry(env, &key, &stats);
+    }
+
+    /// Give a token the original build registered with a bare flag its registry entry
+    fn migrate_token(env: &Env, token: &Address) {
+        if Self::is_supported(env, token) && Self::token_info(env, token).is_none() {
+            let info = Self::add_token(env, token);
+            env.storage().instance().set(&P2PDataKey::SupportedToken(token.clone()), &info);
+        }
+    }
+
+    /// Transfer tokens from user to contract (user already authorized via require_auth)
+    fn transfer_from(
+        env: Env,
+        token: &Address,
+        from: &Address,
+        to: &Address,
+        amount: &i128,
+    ) {
+        use soroban_sdk::IntoVal;
+        let _: () = env.invoke_contract(
+            token,
+            &Symbol::new(&env, "transfer"),
+            (from, to, amount).into_val(&env),
+        );
+    }
+
+    /// Transfer tokens from contract to address
+    fn transfer_tokens(
         env: Env,
+        token: &Address,
+        to: &Address,
+        amount: &i128,
+    ) {
+        use soroban_sdk::IntoVal;
+        let _: () = env.invoke_contract(
+            token,
+            &Symbol::new(&env, "transfer"),
+            (&env.current_contract_address(), to, amount).into_val(&env),
+        );
+    }
+
+    /// Create a funded trade against an offer whose payment is already in escrow,
+    /// reserving its collateral and taking the units off sale
+    fn open_trade(
+        env: &Env,
+        offer: &mut Offer,
         buyer: Address,
-        offer_id: u64,
-    ) -> u64 {
-        buyer.require_auth();
+        units: u32,
+        amount: i128,
+        payment_token: Address,
+        reservation: i128,
+        buyer_key: Option<BytesN<32>>,
+        hashlock: Option<BytesN<32>>,
+    ) -> Result<u64, P2PError> {
+        // Increment trade count
+        let mut trade_count: u64 = env.storage().instance().get(&TRADE_CNT).unwrap_or(0);
+        trade_count += 1;
+
+        let auto_window: u64 = env.storage().instance().get(&AUTO_WIN).ok_or(P2PError::NotInitialized)?;
+
+        // Create trade
+        let trade = Trade {
+            id: trade_count,
+            offer_id: offer.id,
+            buyer: buyer.clone(),
+            vendor: offer.vendor.clone(),
+            units,
+            amount,
+            payment_token,
+            collateral_amount: reservation,
+            milestones: offer.milestones.clone(),
+            milestones_released: 0,
+            released_amount: 0,
+            released_collateral: 0,
+            status: TradeStatus::Funded,
+            delivery_proof: String::from_str(env, ""),
+            buyer_key,
+            sealed_proof: Bytes::new(env),
+            proof_commitment: None,
+            revealed_proof: Bytes::new(env),
+            proof_matches: None,
+            hashlock,
+            preimage: None,
+            created_at: env.ledger().timestamp(),
+            delivered_at: 0,
+            completed_at: 0,
+            dispute_reason: String::from_str(env, ""),
+            vendor_evidence: String::from_str(env, ""),
+            disputed_at: 0,
+            cancel_requested_by: None,
+            auto_complete_at: env.ledger().timestamp() + auto_window,
+            insured_loss: 0,
+            insurance_paid: 0,
+        };
+
+        // Store trade
+        Self::save_trade(env, &trade);
+        env.storage().instance().set(&TRADE_CNT, &trade_count);
+
+        // Update offer
+        offer.collateral.reserved += reservation;
+        offer.quantity_remaining -= units;
+        offer.active_trade_count += 1;
+        offer.updated_at = env.ledger().timestamp();
+
+        if offer.quantity_remaining == 0 {
+            offer.status = OfferStatus::SoldOut;
+            Self::unlist_offer(env, offer);
+        }
+        Self::save_offer(env, offer);
+
+        // Add to buyer's trades
+        Self::add_to_index(env, &P2PDataKey::BuyerTrades(buyer.clone()), trade_count);
+
+        let mut buyer_stats = Self::load_buyer_stats(env, buyer.clone());
+        buyer_stats.total_trades += 1;
+        Self::save_buyer_stats(env, &mut buyer_stats);
+
+        // Add to vendor's trades
+        Self::add_to_index(env, &P2PDataKey::VendorTrades(offer.vendor.clone()), trade_count);
+
+        // Add to offer's trades
+        Self::add_to_index(env, &P2PDataKey::OfferTrades(offer.id), trade_count);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("trade_new"), buyer, offer.vendor.clone()),
+            trade_count
+        );
+
+        Ok(trade_count)
+    }
+
+    /// Load a funded trade the caller can deliver on
+    fn load_deliverable(env: &Env, vendor: &Address, trade_id: u64) -> Result<Trade, P2PError> {
+        let trade: Trade = Self::load_trade(env, trade_id)?;
+
+        if trade.vendor != *vendor {
+            return Err(P2PError::NotTradeVendor);
+        }
+
+        // Hashlocked trades are delivered through claim_htlc
+        if trade.status != TradeStatus::Funded || trade.hashlock.is_some() {
+            return Err(P2PError::InvalidTradeState);
+        }
+
+        Ok(trade)
+    }
+
+    /// Move a trade to Delivered once its proof fields are set
+    fn record_delivery(env: Env, mut trade: Trade) -> Result<(), P2PError> {
+        // Buyer gets a full auto-complete window to inspect the delivery
+        let auto_window: u64 = env.storage().instance().get(&AUTO_WIN).ok_or(P2PError::NotInitialized)?;
+
+        trade.status = TradeStatus::Delivered;
+        trade.delivered_at = env.ledger().timestamp();
+        trade.auto_complete_at = trade.delivered_at + auto_window;
+        trade.cancel_requested_by = None;  // Delivery supersedes any pending cancel
+
+        Self::save_trade(&env, &trade);
+
+        // Track for the auto-completion sweep
+        Self::add_to_index(&env, &P2PDataKey::DeliveredTrades, trade.id);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("delivered"), trade.vendor.clone(), trade.buyer.clone()),
+            trade.id
+        );
+
+        Ok(())
+    }
+
+    /// Release payment to the vendor and the trade's reservation back to the offer
+    fn complete_trade(env: Env, mut trade: Trade, event: Symbol) -> Result<(), P2PError> {
+        // Pay out whatever earlier milestones haven't released
+        let (vendor_payment, fee) = Self::pay_vendor(&env, &trade, trade.amount - trade.released_amount);
+
+        // Get offer to access collateral info
+        let mut offer: Offer = Self::load_offer(&env, trade.offer_id)?;
+
+        // Release the trade's reservation back to the offer's free collateral
+        offer.collateral.reserved -= trade.collateral_amount - trade.released_collateral;
+        trade.released_amount = trade.amount;
+        trade.released_collateral = trade.collateral_amount;
+
+        // Update trade
+        trade.status = TradeStatus::Completed;
+        trade.completed_at = env.ledger().timestamp();
+        Self::save_trade(&env, &trade);
+
+        Self::remove_delivered(&env, trade.id);
+
+        // Update offer
+        offer.active_trade_count -= 1;
+        offer.completed_trade_count += 1;
+        offer.updated_at = env.ledger().timestamp();
+        Self::save_offer(&env, &offer);
+
+        // Update vendor and buyer stats
+        let value = Self::reputation_value(&env, &trade);
+        Self::update_vendor_stats(env.clone(), trade.vendor.clone(), value, TradeOutcome::Completed, 0);
+
+        let mut buyer_stats = Self::load_buyer_stats(&env, trade.buyer.clone());
+        buyer_stats.completed_trades += 1;
+        buyer_stats.total_volume += trade.amount;
+        Self::save_buyer_stats(&env, &mut buyer_stats);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (event, trade.buyer.clone(), trade.vendor.clone()),
+            (trade.id, vendor_payment, fee)
+        );
+
+        Ok(())
+    }
+
+    /// Settle an accepted delivery: release the next milestone, or complete the
+    /// trade when it has no milestones left
+    fn settle_delivery(env: Env, trade: Trade, event: Symbol) -> Result<(), P2PError> {
+        if trade.milestones_released + 1 < trade.milestones.len() {
+            Self::release_milestone(env, trade)
+        } else {
+            Self::complete_trade(env, trade, event)
+        }
+    }
+
+    /// Pay one milestone's slice and its reservation, then await the next delivery
+    fn release_milestone(env: Env, mut trade: Trade) -> Result<(), P2PError> {
+        let share = trade.milestones.get(trade.milestones_released).unwrap() as i128;
+        let amount = trade.amount * share / BPS_DENOM as i128;
+        let reservation = trade.collateral_amount * share / BPS_DENOM as i128;
+
+        let (vendor_payment, fee) = Self::pay_vendor(&env, &trade, amount);
+
+        let mut offer: Offer = Self::load_offer(&env, trade.offer_id)?;
+        offer.collateral.reserved -= reservation;
+        offer.updated_at = env.ledger().timestamp();
+        Self::save_offer(&env, &offer);
+
+        // Next milestone is due within a fresh auto-complete window
+        let auto_window: u64 = env.storage().instance().get(&AUTO_WIN).ok_or(P2PError::NotInitialized)?;
+
+        trade.released_amount += amount;
+        trade.released_collateral += reservation;
+        trade.milestones_released += 1;
+        trade.status = TradeStatus::Funded;
+        trade.auto_complete_at = env.ledger().timestamp() + auto_window;
+        Self::save_trade(&env, &trade);
+
+        Self::remove_delivered(&env, trade.id);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("milestone"), trade.buyer.clone(), trade.vendor.clone()),
+            (trade.id, trade.milestones_released, vendor_payment, fee)
+        );
+
+        Ok(())
+    }
+
+    /// Pay the vendor `amount` of the escrow less the protocol fee, which is
+    /// held for the treasury. Returns (vendor_payment, fee).
+    fn pay_vendor(env: &Env, trade: &Trade, amount: i128) -> (i128, i128) {
+        let fee_bps: u32 = env.storage().instance().get(&FEE_BPS).unwrap_or(0);
+        let fee = (amount * fee_bps as i128) / BPS_DENOM as i128;
+        let vendor_payment = amount - fee;
+
+        if vendor_payment > 0 {
+            Self::transfer_tokens(
+                env.clone(),
+                &trade.payment_token,
+                &trade.vendor,
+                &vendor_payment,
+            );
+        }
+
+        if fee > 0 {
+            let fee_share: u32 = env.storage().instance().get(&INS_FEE).unwrap_or(0);
+            let insured = (fee * fee_share as i128) / BPS_DENOM as i128;
+            Self::fund_insurance(env, &trade.payment_token, insured);
+
+            let key = P2PDataKey::FeeBalance(trade.payment_token.clone());
+            let collected: i128 = env.storage().instance().get(&key).unwrap_or(0);
+            env.storage().instance().set(&key, &(collected + fee - insured));
+        }
+
+        (vendor_payment, fee)
+    }
+
+    /// Credit the insurance fund for a token
+    fn fund_insurance(env: &Env, token: &Address, amount: i128) {
+        if amount <= 0 {
+            return;
+        }
+
+        let key = P2PDataKey::InsuranceBalance(token.clone());
+        let balance: i128 = env.storage().instance().get(&key).unwrap_or(0);
+        env.storage().instance().set(&key, &(balance + amount));
+
+        env.events().publish((symbol_short!("ins_in"), token.clone()), (amount, balance + amount));
+    }
+
+    /// Refund the buyer in full and release the reservation without a ruling
+    fn cancel_trade(env: Env, mut trade: Trade) -> Result<(), P2PError> {
+        // Refund buyer whatever milestones or an HTLC claim haven't released
+        let refund = trade.amount - trade.released_amount;
+        if refund > 0 {
+            Self::transfer_tokens(env.clone(), &trade.payment_token, &trade.buyer, &refund);
+        }
+
+        let mut offer: Offer = Self::load_offer(&env, trade.offer_id)?;
+
+        offer.collateral.reserved -= trade.collateral_amount - trade.released_collateral;
+        offer.active_trade_count -= 1;
+
+        // Undelivered units go back on sale, unless work was already paid for
+        if trade.status == TradeStatus::Funded && trade.released_amount == 0 {
+            offer.quantity_remaining += trade.units;
+            if offer.status == OfferStatus::SoldOut && offer.request_id == 0 {
+                offer.status = OfferStatus::Active;
+                Self::list_offer(&env, &offer);
+            }
+        } else {
+            Self::remove_delivered(&env, trade.id);
+        }
+
+        offer.updated_at = env.ledger().timestamp();
+        Self::save_offer(&env, &offer);
+
+        trade.status = TradeStatus::Cancelled;
+        trade.completed_at = env.ledger().timestamp();
+        Self::save_trade(&env, &trade);
+
+        let value = Self::reputation_value(&env, &trade);
+        Self::update_vendor_stats(env.clone(), trade.vendor.clone(), value, TradeOutcome::Cancelled, 0);
+
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
+
+        env.events().publish(
+            (symbol_short!("cancelled"), trade.buyer.clone(), trade.vendor.clone()),
+            trade.id
+        );
+
+        Ok(())
+    }
+
+    /// Drop a trade from the auto-completion sweep index
+    fn remove_delivered(env: &Env, trade_id: u64) {
+        Self::remove_from_index(env, &P2PDataKey::DeliveredTrades, trade_id);
+    }
+
+    /// Add an offer to the active and category listing indexes
+    fn list_offer(env: &Env, offer: &Offer) {
+        Self::add_to_index(env, &P2PDataKey::CategoryOffers(offer.category.clone()), offer.id);
+        Self::add_to_index(env, &P2PDataKey::ActiveOffers, offer.id);
+    }
+
+    /// Remove an offer from the active and category listing indexes
+    fn unlist_offer(env: &Env, offer: &Offer) {
+        Self::remove_from_index(env, &P2PDataKey::CategoryOffers(offer.category.clone()), offer.id);
+        Self::remove_from_index(env, &P2PDataKey::ActiveOffers, offer.id);
+    }
+
+    /// Append an ID to a stored Vec<u64> index
+    fn add_to_index(env: &Env, key: &P2PDataKey, id: u64) {
+        let mut ids = Self::load_index(env, key);
+        ids.push_back(id);
+        Self::write_entry(env, key, &ids);
+    }
+
+    /// Remove an ID from a stored Vec<u64> index
+    fn remove_from_index(env: &Env, key: &P2PDataKey, id: u64) {
+        let ids = Self::load_index(env, key);
+        let mut kept: Vec<u64> = Vec::new(env);
+        for i in 0..ids.len() {
+            let existing = ids.get(i).unwrap();
+            if existing != id {
+                kept.push_back(existing);
+            }
+        }
+        Self::write_entry(env, key, &kept);
+    }
+
+    /// Pay out a dispute ruling from the trade's escrow and collateral reservation.
+    /// `fault` is the vendor outcome recorded if any collateral gets slashed.
+    fn settle_dispute(
+        env: Env,
+        mut trade: Trade,
+        buyer_share_bps: u32,
+        collateral_slash_bps: u32,
+        fault: TradeOutcome,
+    ) -> Result<(), P2PError> {
+        let mut offer: Offer = Self::load_offer(&env, trade.offer_id)?;
+
+        // Split the escrow still held; released milestones are not in dispute
+        let outstanding = trade.amount - trade.released_amount;
+        let buyer_refund = (outstanding * buyer_share_bps as i128) / BPS_DENOM as i128;
+        let vendor_payment = outstanding - buyer_refund;
+
+        if buyer_refund > 0 {
+            Self::transfer_tokens(env.clone(), &trade.payment_token, &trade.buyer, &buyer_refund);
+        }
+        if vendor_payment > 0 {
+            Self::transfer_tokens(env.clone(), &trade.payment_token, &trade.vendor, &vendor_payment);
+        }
 
-        let mut offer: Offer = env.storage().instance()
-            .get(&P2PDataKey::Offer(offer_id))
-            .unwrap_or_else(|| panic!("Offer not found"));
+        // Slash from this trade's reservation only, release the remainder
+        let reserved = trade.collateral_amount - trade.released_collateral;
+        let slashed = (reserved * collateral_slash_bps as i128) / BPS_DENOM as i128;
+        Self::ensure_liquid(&env, &mut offer, slashed)?;
 
-        if offer.status != OfferStatus::Active {
-            panic!("Offer not active");
+        // Part of the slash feeds the insurance fund, the rest goes to the buyer
+        let slash_share: u32 = env.storage().instance().get(&INS_SLASH).unwrap_or(0);
+        let insured = (slashed * slash_share as i128) / BPS_DENOM as i128;
+        let buyer_slash = slashed - insured;
+        if buyer_slash > 0 {
+            Self::transfer_tokens(
+                env.clone(),
+                &offer.collateral.token_address,
+                &trade.buyer,
+                &buyer_slash,
+            );
         }
+        Self::fund_insurance(&env, &offer.collateral.token_address, insured);
+        offer.collateral.amount -= slashed;
+        offer.collateral.reserved -= reserved;
 
-        if offer.vendor == buyer {
-            panic!("Cannot trade with yourself");
+        // An arbiter ruling entitles the buyer to their share of the payment in dispute:
+        // the unreleased escrow, or all of it once an HTLC claim has paid the vendor.
+        // Whatever the refund and the buyer's slash didn't cover can be claimed from
+        // insurance. Collateral in another token is valued at the oracle price; with no
+        // usable price the loss can't be measured, so nothing is claimable.
+        if fault == TradeOutcome::Fraud {
+            let disputed = if trade.hashlock.is_some() { trade.amount } else { outstanding };
+            let entitled = (disputed * buyer_share_bps as i128) / BPS_DENOM as i128;
+            let slash_value = if buyer_slash == 0 || offer.collateral.token_address == trade.payment_token {
+                Ok(buyer_slash)
+            } else {
+                Self::to_reference_amount(&env, &offer.collateral.token_address, buyer_slash)
+                    .and_then(|value| Self::to_token_amount(&env, &trade.payment_token, value))
+            };
+            trade.insured_loss = match slash_value {
+                Ok(covered) => (entitled - buyer_refund - covered).max(0),
+                Err(_) => 0,
+            };
         }
 
-        // Transfer payment from buyer to contract (escrow)
-        Self::transfer_from(
-            env.clone(),
-            &offer.payment_token,
-            &buyer,
-            &env.current_contract_address(),
-            &offer.price,
-        );
+        // Update trade
+        trade.status = TradeStatus::Resolved;
+        trade.completed_at = env.ledger().timestamp();
+        Self::save_trade(&env, &trade);
 
-        // Increment trade count
-        let mut trade_count: u64 = env.storage().instance().get(&TRADE_CNT).unwrap_or(0);
-        trade_count += 1;
+        // Update offer
+        offer.active_trade_count -= 1;
+        offer.updated_at = env.ledger().timestamp();
+        Self::save_offer(&env, &offer);
 
-        let auto_window: u64 = env.storage().instance().get(&AUTO_WIN).unwrap();
+        // The ruling holds the larger of the refund and slash shares against the
+        // vendor; awarding the buyer neither vindicates them
+        let fault_bps = buyer_share_bps.max(collateral_slash_bps);
+        let outcome = if fault_bps == 0 { TradeOutcome::Completed } else { fault };
+        let value = Self::reputation_value(&env, &trade);
+        Self::update_vendor_stats(env.clone(), trade.vendor.clone(), value, outcome, fault_bps);
 
-        // Create trade
-        let trade = Trade {
-            id: trade_count,
-            offer_id,
-            buyer: buyer.clone(),
-            vendor: offer.vendor.clone(),
-            amount: offer.price,
-            payment_token: offer.payment_token.clone(),
-            collateral_amount: offer.collateral.amount,
-            status: TradeStatus::Funded,
-            delivery_proof: String::from_str(&env, ""),
-            created_at: env.ledger().timestamp(),
-            delivered_at: 0,
-            completed_at: 0,
-            dispute_reason: String::from_str(&env, ""),
-            auto_complete_at: env.ledger().timestamp() + auto_window,
-        };
+        // Buyer lost the dispute if the ruling awarded them nothing; a refund of
+        // zero alone isn't enough, since claimed HTLC trades have no escrow left
+        if buyer_share_bps == 0 && collateral_slash_bps == 0 {
+            let mut buyer_stats = Self::load_buyer_stats(&env, trade.buyer.clone());
+            buyer_stats.disputes_lost += 1;
+            Self::save_buyer_stats(&env, &mut buyer_stats);
+        }
 
-        // Store trade
-        env.storage().instance().set(&P2PDataKey::Trade(trade_count), &trade);
-        env.storage().instance().set(&TRADE_CNT, &trade_count);
+        env.storage().instance().extend_ttl(INSTANCE_TTL_THRESHOLD, INSTANCE_TTL_EXTEND);
 
-        // Update offer
-        offer.active_trade_count += 1;
-        offer.updated_at = env.ledger().timestamp();
-        env.storage().instance().set(&P2PDataKey::Offer(offer_id), &offer);
+        env.events().publish(
+            (symbol_short!("resolved"), trade.buyer.clone(), trade.vendor.clone()),
+            (trade.id, buyer_refund, slashed)
+        );
 
-        // Add to buyer's trades
-        let mut buyer_trades: Vec<u64> = env.storage().instance()
-            .get(&P2PDataKey::BuyerTrades(buyer.clone()))
-            .unwrap_or(Vec::new(&env));
-        buyer_trades.push_back(trade_count);
-        env.storage().instance().set(&P2PDataKey::BuyerTrades(buyer.clone()), &buyer_trades);
+        Ok(())
+    }
 
-        // Add to vendor's trades
-        let mut vendor_trades: Vec<u64> = env.storage().instance()
-            .get(&P2PDataKey::VendorTrades(offer.vendor.clone()))
-            .unwrap_or(Vec::new(&env));
-        vendor_trades.push_back(trade_count);
-        env.storage().instance().set(&P2PDataKey::VendorTrades(offer.vendor.clone()), &vendor_trades);
+    /// Supply tokens held by the contract to the pool, authorising the pool's
+    /// nested pull of them
+    fn supply_to_pool(env: &Env, pool: &Address, token: &Address, amount: i128) {
+        let this = env.current_contract_address();
+        env.authorize_as_current_contract(vec![
+            env,
+            InvokerContractAuthEntry::Contract(SubContractInvocation {
+                context: ContractContext {
+                    contract: token.clone(),
+                    fn_name: Symbol::new(env, "transfer"),
+                    args: (this.clone(), pool.clone(), amount).into_val(env),
+                },
+                sub_invocations: Vec::new(env),
+            }),
+        ]);
+        YieldPoolClient::new(env, pool).supply(&this, token, &amount);
+    }
 
-        // Add to offer's trades
-        let mut offer_trades: Vec<u64> = env.storage().instance()
-            .get(&P2PDataKey::OfferTrades(offer_id))
-            .unwrap_or(Vec::new(&env));
-        offer_trades.push_back(trade_count);
-        env.storage().instance().set(&P2PDataKey::OfferTrades(offer_id), &offer_trades);
+    /// Recall the offer's pool position if the collateral held here can't cover `needed`
+    fn ensure_liquid(env: &Env, offer: &mut Offer, needed: i128) -> Result<(), P2PError> {
+        if offer.collateral.amount - offer.collateral.supplied < needed {
+            Self::recall_from_pool(env, offer)?;
+        }
+        Ok(())
+    }
+
+    /// Withdraw the offer's whole pool position and credit the interest to its
+    /// collateral. Fails with PoolIlliquid, leaving everything in place, if the
+    /// pool can't return it all. Returns the interest earned.
+    fn recall_from_pool(env: &Env, offer: &mut Offer) -> Result<i128, P2PError> {
+        if offer.collateral.pool_shares == 0 {
+            return Ok(0);
+        }
+
+        let pool_address: Address = env.storage().instance().get(&YIELD_POOL).ok_or(P2PError::PoolNotSet)?;
+        let pool = YieldPoolClient::new(env, &pool_address);
+        let token = offer.collateral.token_address.clone();
+        let this = env.current_contract_address();
+
+        let shares_key = P2PDataKey::PoolShares(token.clone());
+        let total_shares: i128 = env.storage().instance().get(&shares_key).unwrap_or(0);
+        let value = offer.collateral.pool_shares * pool.balance(&this, &token) / total_shares;
+
+        // A partial return goes straight back so the position stays whole
+        let returned = pool.withdraw(&this, &token, &value, &this);
+        if returned < value {
+            if returned > 0 {
+                Self::supply_to_pool(env, &pool_address, &token, returned);
+            }
+            return Err(P2PError::PoolIlliquid);
+        }
 
-        env.storage().instance().extend_ttl(5000, 5000);
+        let earned = value - offer.collateral.supplied;
+        env.storage().instance().set(&shares_key, &(total_shares - offer.collateral.pool_shares));
+        offer.collateral.amount += earned;
+        offer.collateral.yield_accrued += earned;
+        offer.collateral.supplied = 0;
+        offer.collateral.pool_shares = 0;
 
         env.events().publish(
-            (symbol_short!("trade_new"), buyer, offer.vendor),
-            trade_count
+            (symbol_short!("recalled"), offer.vendor.clone(), offer.id),
+            (value, earned)
         );
 
-        trade_count
+        Ok(earned)
     }
 
-    /// Vendor marks trade as delivered with proof
-    pub fn mark_delivered(
-        env: Env,
-        vendor: Address,
-        trade_id: u64,
-        delivery_proof: String,
-    ) {
-        vendor.require_auth();
+    /// Collateral required to back a trade of the given value, at the collateral
+    /// token's own floor when the registry sets one
+    fn required_collateral(env: &Env, collateral_token: &Address, amount: i128) -> Result<i128, P2PError> {
+        let min_percent = match Self::token_info(env, collateral_token) {
+            Some(info) if info.min_collateral_percent > 0 => info.min_collateral_percent,
+            _ => env.storage().instance().get(&MIN_COLL).ok_or(P2PError::NotInitialized)?,
+        };
+        Ok((amount * min_percent as i128) / 100)
+    }
 
-        let mut trade: Trade = env.storage().instance()
-            .get(&P2PDataKey::Trade(trade_id))
-            .unwrap_or_else(|| panic!("Trade not found"));
+    /// Collateral needed to back the offer's unsold stock
+    fn stock_collateral(env: &Env, offer: &Offer) -> Result<i128, P2PError> {
+        Self::required_collateral(
+            env,
+            &offer.collateral.token_address,
+            Self::collateral_value(env, offer, offer.quantity_remaining)?,
+        )
+    }
 
-        if trade.vendor != vendor {
-            panic!("Not trade vendor");
+    /// What `units` cost in `token`: a fixed payment option, the oracle
+    /// conversion of a reference price, or the offer's own price
+    fn payment_amount(env: &Env, offer: &Offer, token: &Address, units: u32) -> Result<i128, P2PError> {
+        if let Some(option) = offer.payment_options.iter().find(|option| option.token == *token) {
+            return Ok(option.price * units as i128);
         }
 
-        if trade.status != TradeStatus::Funded {
-            panic!("Trade not in funded state");
+        let accepted = *token == offer.payment_token
+            || (offer.ref_price > 0 && offer.accepted_tokens.contains(token));
+        if !accepted {
+            return Err(P2PError::TokenNotSupported);
         }
 
-        trade.status = TradeStatus::Delivered;
-        trade.delivery_proof = delivery_proof;
-        trade.delivered_at = env.ledger().timestamp();
+        if offer.ref_price == 0 {
+            Ok(offer.price * units as i128)
+        } else {
+            Self::to_token_amount(env, token, offer.ref_price * units as i128)
+        }
+    }
+
+    /// Value of `units` in the offer's collateral token, through the oracle
+    /// when the offer is reference-priced
+    fn collateral_value(env: &Env, offer: &Offer, units: u32) -> Result<i128, P2PError> {
+        if offer.ref_price == 0 {
+            return Ok(offer.price * units as i128);
+        }
+        Self::to_token_amount(env, &offer.collateral.token_address, offer.ref_price * units as i128)
+    }
 
-        env.storage().instance().set(&P2PDataKey::Trade(trade_id), &trade);
-        env.storage().instance().extend_ttl(5000, 5000);
+    /// Convert a USD-cent amount into `token`'s smallest unit at the oracle's
+    /// latest price, rounding up so the vendor is never short
+    fn to_token_amount(env: &Env, token: &Address, reference_amount: i128) -> Result<i128, P2PError> {
+        let (price, price_decimals, token_decimals) = Self::oracle_price(env, token)?;
 
-        env.events().publish(
-            (symbol_short!("delivered"), vendor, trade.buyer.clone()),
-            trade_id
-        );
+        // reference_amount * 10^(price + token decimals) / (price * 10^REFERENCE_DECIMALS), rounded up.
+        // The price scale is applied to quotient and remainder separately so large amounts in
+        // high-decimal tokens stay within i128.
+        let price_scale = 10i128.checked_pow(price_decimals).ok_or(P2PError::InvalidAmount)?;
+        let numerator = 10i128
+            .checked_pow(token_decimals)
+            .and_then(|scale| reference_amount.checked_mul(scale))
+            .ok_or(P2PError::InvalidAmount)?;
+        let denominator = price.checked_mul(10i128.pow(REFERENCE_DECIMALS)).ok_or(P2PError::InvalidAmount)?;
+
+        let remainder = (numerator % denominator).checked_mul(price_scale).ok_or(P2PError::InvalidAmount)?;
+        (numerator / denominator)
+            .checked_mul(price_scale)
+            .and_then(|whole| whole.checked_add(remainder.checked_add(denominator - 1)? / denominator))
+            .ok_or(P2PError::InvalidAmount)
     }
 
-    /// Buyer confirms delivery - releases payment and collateral
-    pub fn confirm_delivery(
-        env: Env,
-        buyer: Address,
-        trade_id: u64,
-    ) {
-        buyer.require_auth();
+    /// Value an amount of `token` in USD cents at the oracle's latest price,
+    /// rounding down
+    fn to_reference_amount(env: &Env, token: &Address, amount: i128) -> Result<i128, P2PError> {
+        let (price, price_decimals, token_decimals) = Self::oracle_price(env, token)?;
 
-        let mut trade: Trade = env.storage().instance()
-            .get(&P2PDataKey::Trade(trade_id))
-            .unwrap_or_else(|| panic!("Trade not found"));
+        // amount * price * 10^REFERENCE_DECIMALS / 10^(price + token decimals)
+        let scale = 10i128.checked_pow(price_decimals + token_decimals).ok_or(P2PError::InvalidAmount)?;
+        let product = amount.checked_mul(price).ok_or(P2PError::InvalidAmount)?;
+        let reference_scale = 10i128.pow(REFERENCE_DECIMALS);
+        (product / scale)
+            .checked_mul(reference_scale)
+            .and_then(|whole| whole.checked_add((product % scale).checked_mul(reference_scale)? / scale))
+            .ok_or(P2PError::InvalidAmount)
+    }
 
-        if trade.buyer != buyer {
-            panic!("Not trade buyer");
+    /// Oracle's latest price for `token` with the feed's and the token's decimals
+    fn oracle_price(env: &Env, token: &Address) -> Result<(i128, u32, u32), P2PError> {
+        let oracle: Address = env.storage().instance().get(&ORACLE).ok_or(P2PError::PriceUnavailable)?;
+        let max_age: u64 = env.storage().instance().get(&ORA_AGE).ok_or(P2PError::PriceUnavailable)?;
+
+        let oracle = PriceOracleClient::new(env, &oracle);
+        let quote = oracle
+            .lastprice(&Asset::Stellar(token.clone()))
+            .ok_or(P2PError::PriceUnavailable)?;
+
+        if quote.price <= 0 {
+            return Err(P2PError::PriceUnavailable);
         }
 
-        if trade.status != TradeStatus::Delivered {
-            panic!("Trade not delivered");
+        if env.ledger().timestamp().saturating_sub(quote.timestamp) > max_age {
+            return Err(P2PError::StalePrice);
         }
 
-        // Release payment to vendor
-        Self::transfer_tokens(
-            env.clone(),
-            &trade.payment_token,
-            &trade.vendor,
-            &trade.amount,
-        );
+        let token_decimals = match Self::token_info(env, token) {
+            Some(info) => info.decimals,
+            None => token::TokenClient::new(env, token).decimals(),
+        };
 
-        // Get offer to access collateral info
-        let mut offer: Offer = env.storage().instance()
-            .get(&P2PDataKey::Offer(trade.offer_id))
-            .unwrap_or_else(|| panic!("Offer not found"));
+        Ok((quote.price, oracle.decimals(), token_decimals))
+    }
 
-        // Return collateral to vendor
-        Self::transfer_tokens(
-            env.clone(),
-            &offer.collateral.token_address,
-            &trade.vendor,
-            &trade.collateral_amount,
-        );
+    /// Offer can still be managed by its vendor
+    fn is_open(offer: &Offer) -> bool {
+        matches!(
+            offer.status,
+            OfferStatus::Active | OfferStatus::Paused | OfferStatus::SoldOut
+        )
+    }
 
-        // Update trade
-        trade.status = TradeStatus::Completed;
-        trade.completed_at = env.ledger().timestamp();
-        env.storage().instance().set(&P2PDataKey::Trade(trade_id), &trade);
+    /// Token was registered by the admin
+    fn is_supported(env: &Env, token: &Address) -> bool {
+        env.storage().instance().has(&P2PDataKey::SupportedToken(token.clone()))
+    }
 
-        // Update offer
-        offer.active_trade_count -= 1;
-        offer.completed_trade_count += 1;
-        offer.updated_at = env.ledger().timestamp();
-        env.storage().instance().set(&P2PDataKey::Offer(trade.offer_id), &offer);
+    /// Registry entry; the original build's bare `true` flag reads as missing
+    fn token_info(env: &Env, token: &Address) -> Option<TokenInfo> {
+        let value: Option<Val> = env.storage().instance().get(&P2PDataKey::SupportedToken(token.clone()));
+        value.and_then(|value| TokenInfo::try_from_val(env, &value).ok())
+    }
 
-        // Update vendor stats
-        Self::update_vendor_stats(env.clone(), trade.vendor.clone(), trade.amount, true);
+    /// Append a token to the registry list and read its metadata
+    fn add_token(env: &Env, token: &Address) -> TokenInfo {
+        let mut tokens = Self::supported_tokens(env);
+        tokens.push_back(token.clone());
+        env.storage().instance().set(&P2PDataKey::SupportedTokens, &tokens);
 
-        env.storage().instance().extend_ttl(5000, 5000);
+        let client = token::TokenClient::new(env, token);
+        TokenInfo {
+            token: token.clone(),
+            symbol: client.symbol(),
+            decimals: client.decimals(),
+            min_collateral_percent: 0,
+            min_trade: 0,
+            max_trade: 0,
+            registered_at: env.ledger().timestamp(),
+        }
+    }
 
-        env.events().publish(
-            (symbol_short!("completed"), buyer, trade.vendor.clone()),
-            trade_id
-        );
+    fn load_token_info(env: &Env, token: &Address) -> Result<TokenInfo, P2PError> {
+        Self::token_info(env, token).ok_or(P2PError::TokenNotSupported)
     }
 
-    /// Buyer disputes trade - claims collateral
-    pub fn dispute_trade(
-        env: Env,
-        buyer: Address,
-        trade_id: u64,
-        reason: String,
-    ) {
-        buyer.require_auth();
+    fn supported_tokens(env: &Env) -> Vec<Address> {
+        env.storage().instance()
+            .get(&P2PDataKey::SupportedTokens)
+            .unwrap_or(Vec::new(env))
+    }
 
-        let mut trade: Trade = env.storage().instance()
-            .get(&P2PDataKey::Trade(trade_id))
-            .unwrap_or_else(|| panic!("Trade not found"));
+    /// Refuse a trade payment outside the token's configured limits
+    fn check_trade_limits(env: &Env, token: &Address, amount: i128) -> Result<(), P2PError> {
+        let info = Self::load_token_info(env, token)?;
+        if amount < info.min_trade || (info.max_trade != 0 && amount > info.max_trade) {
+            return Err(P2PError::InvalidAmount);
+        }
+        Ok(())
+    }
 
-        if trade.buyer != buyer {
-            panic!("Not trade buyer");
+    /// Offer has passed its expiry time
+    fn is_expired(env: &Env, offer: &Offer) -> bool {
+        offer.expires_at != 0 && env.ledger().timestamp() >= offer.expires_at
+    }
+
+    /// Drop offers that have expired but not yet been swept, reading one expiry
+    /// map rather than every listed offer
+    fn unexpired(env: &Env, offer_ids: Vec<u64>) -> Vec<u64> {
+        let expiries: Map<u64, u64> = Self::read_entry(env, &P2PDataKey::OfferExpiries)
+            .unwrap_or(Map::new(env));
+        if expiries.is_empty() {
+            return offer_ids;
         }
 
-        // Can dispute if delivered but not satisfied, or if auto-complete window passed
         let now = env.ledger().timestamp();
-        let can_dispute = (trade.status == TradeStatus::Delivered) ||
-                         (trade.status == TradeStatus::Funded && now > trade.auto_complete_at);
+        let mut live: Vec<u64> = Vec::new(env);
+        for offer_id in offer_ids.iter() {
+            if expiries.get(offer_id).is_none_or(|expires_at| now < expires_at) {
+                live.push_back(offer_id);
+            }
+        }
+        live
+    }
 
-        if !can_dispute {
-            panic!("Cannot dispute at this stage");
+    /// Track an offer's expiry for the listing queries; 0 stops tracking it
+    fn record_expiry(env: &Env, offer_id: u64, expires_at: u64) {
+        let mut expiries: Map<u64, u64> = Self::read_entry(env, &P2PDataKey::OfferExpiries)
+            .unwrap_or(Map::new(env));
+        if expires_at == 0 {
+            if !expiries.contains_key(offer_id) {
+                return;
+            }
+            expiries.remove(offer_id);
+        } else {
+            expiries.set(offer_id, expires_at);
         }
+        Self::write_entry(env, &P2PDataKey::OfferExpiries, &expiries);
+    }
 
-        // Get offer for collateral info
-        let mut offer: Offer = env.storage().instance()
-            .get(&P2PDataKey::Offer(trade.offer_id))
-            .unwrap_or_else(|| panic!("Offer not found"));
+    /// Locked collateral not reserved by open trades
+    fn free_collateral(offer: &Offer) -> i128 {
+        offer.collateral.amount - offer.collateral.reserved
+    }
 
-        // Return payment to buyer
-        Self::transfer_tokens(
-            env.clone(),
-            &trade.payment_token,
-            &buyer,
-            &trade.amount,
-        );
+    /// Trade payment rescaled to REPUTATION_DECIMALS, so a whole unit of any
+    /// registered token weighs the same whatever its decimals
+    fn reputation_value(env: &Env, trade: &Trade) -> i128 {
+        let decimals = Self::token_info(env, &trade.payment_token)
+            .map_or(REPUTATION_DECIMALS, |info| info.decimals);
 
-        // Transfer collateral to buyer (penalty for vendor)
-        Self::transfer_tokens(
-            env.clone(),
-            &offer.collateral.token_address,
-            &buyer,
-            &trade.collateral_amount,
-        );
+        if decimals >= REPUTATION_DECIMALS {
+            trade.amount / 10i128.pow(decimals - REPUTATION_DECIMALS)
+        } else {
+            trade.amount.saturating_mul(10i128.pow(REPUTATION_DECIMALS - decimals))
+        }
+    }
 
-        // Update trade
-        trade.status = TradeStatus::DisputedBuyer;
-        trade.dispute_reason = reason;
-        trade.completed_at = env.ledger().timestamp();
-        env.storage().instance().set(&P2PDataKey::Trade(trade_id), &trade);
+    /// Update vendor statistics and reputation; `value` is from reputation_value.
+    /// A ruling holds `fault_bps` of the value against the vendor and credits the rest.
+    fn update_vendor_stats(
+        env: Env,
+        vendor: Address,
+        value: i128,
+        outcome: TradeOutcome,
+        fault_bps: u32,
+    ) {
+        let mut stats = Self::load_vendor_stats(&env, vendor.clone());
+        Self::decay_vendor_stats(&env, &mut stats);
 
-        // Update offer
-        offer.active_trade_count -= 1;
-        offer.updated_at = env.ledger().timestamp();
-        env.storage().instance().set(&P2PDataKey::Offer(trade.offer_id), &offer);
+        if outcome == TradeOutcome::Cancelled {
+            stats.cancelled_trades += 1;
+            stats.neutral_volume += value;
+        } else {
+            stats.total_trades += 1;
+            stats.total_volume += value;
+        }
+
+        let against = value * fault_bps as i128 / BPS_DENOM as i128;
+        match outcome {
+            TradeOutcome::Completed => {
+                stats.completed_trades += 1;
+                stats.positive_volume += value;
+            }
+            TradeOutcome::Defaulted => {
+                stats.disputed_trades += 1;
+                stats.positive_volume += value - against;
+                stats.negative_volume += against;
+            }
+            TradeOutcome::Fraud => {
+                stats.disputed_trades += 1;
+                stats.fraud_trades += 1;
+                stats.positive_volume += value - against;
+                stats.negative_volume += against * FRAUD_WEIGHT;
+            }
+            TradeOutcome::Cancelled => {}
+        }
 
-        // Update vendor stats (negative)
-        Self::update_vendor_stats(env.clone(), trade.vendor.clone(), trade.amount, false);
+        stats.reputation_score = Self::reputation_breakdown(&env, &stats).score;
+        Self::write_entry(&env, &P2PDataKey::VendorStats(vendor), &stats);
+    }
 
-        env.storage().instance().extend_ttl(5000, 5000);
+    /// Record a vendor-declined trade against their reputation
+    fn record_vendor_decline(env: &Env, vendor: Address, value: i128) {
+        let mut stats = Self::load_vendor_stats(env, vendor.clone());
+        Self::decay_vendor_stats(env, &mut stats);
 
-        env.events().publish(
-            (symbol_short!("disputed"), buyer, trade.vendor.clone()),
-            trade_id
-        );
+        stats.declined_trades += 1;
+        stats.declined_volume += value;
+
+        stats.reputation_score = Self::reputation_breakdown(env, &stats).score;
+        Self::write_entry(env, &P2PDataKey::VendorStats(vendor), &stats);
     }
 
-    // ========================================================================
-    // QUERY FUNCTIONS
-    // ========================================================================
+    /// Fold a buyer's star rating into the vendor's average and weighted reputation
+    fn record_vendor_rating(env: &Env, vendor: Address, value: i128, stars: u32) {
+        let mut stats = Self::load_vendor_stats(env, vendor.clone());
+        Self::decay_vendor_stats(env, &mut stats);
 
-    pub fn get_offer(env: Env, offer_id: u64) -> Offer {
-        env.storage().instance()
-            .get(&P2PDataKey::Offer(offer_id))
-            .unwrap_or_else(|| panic!("Offer not found"))
+        stats.rating_average = Self::rolling_average(stats.rating_average, stats.rating_count, stars);
+        stats.rating_count += 1;
+
+        // 1 star earns none of the trade's value, 5 stars earn all of it
+        stats.rated_volume += value;
+        stats.rating_credit += value * (stars - 1) as i128 / (MAX_STARS - 1) as i128;
+
+        stats.reputation_score = Self::reputation_breakdown(env, &stats).score;
+        Self::write_entry(env, &P2PDataKey::VendorStats(vendor), &stats);
     }
 
-    pub fn get_trade(env: Env, trade_id: u64) -> Trade {
-        env.storage().instance()
-            .get(&P2PDataKey::Trade(trade_id))
-            .unwrap_or_else(|| panic!("Trade not found"))
+    /// Add one rating to a mean kept as stars x100
+    fn rolling_average(average: u32, count: u32, stars: u32) -> u32 {
+        (average * count + stars * 100) / (count + 1)
     }
 
-    pub fn get_vendor_offers(env: Env, vendor: Address) -> Vec<u64> {
-        env.storage().instance()
-            .get(&P2PDataKey::VendorOffers(vendor))
-            .unwrap_or(Vec::new(&env))
+    /// Buyers can pay for the offer in `token`
+    fn accepts_token(offer: &Offer, token: &Address) -> bool {
+        *token == offer.payment_token
+            || offer.payment_options.iter().any(|option| option.token == *token)
+            || (offer.ref_price > 0 && offer.accepted_tokens.contains(token))
     }
 
-    pub fn get_category_offers(env: Env, category: Category) -> Vec<u64> {
-        env.storage().instance()
-            .get(&P2PDataKey::CategoryOffers(category))
-            .unwrap_or(Vec::new(&env))
+    fn offer_matches(env: &Env, offer: &Offer, filter: &OfferFilter) -> bool {
+        if offer.request_id != 0 || (Self::is_open(offer) && Self::is_expired(env, offer)) {
+            return false;
+        }
+        if !filter.statuses.is_empty() && !filter.statuses.contains(&offer.status) {
+            return false;
+        }
+        if !filter.categories.is_empty() && !filter.categories.contains(&offer.category) {
+            return false;
+        }
+        if let Some(token) = &filter.payment_token {
+            if !Self::accepts_token(offer, token) {
+                return false;
+            }
+        }
+        if filter.min_price.is_some_and(|min| offer.price < min)
+            || filter.max_price.is_some_and(|max| offer.price > max)
+        {
+            return false;
+        }
+        if let Some(min_reputation) = filter.min_reputation {
+            let mut stats = Self::load_vendor_stats(env, offer.vendor.clone());
+            Self::decay_vendor_stats(env, &mut stats);
+            if Self::reputation_breakdown(env, &stats).score < min_reputation {
+                return false;
+            }
+        }
+        true
     }
 
-    pub fn get_active_offers(env: Env) -> Vec<u64> {
-        env.storage().instance()
-            .get(&P2PDataKey::ActiveOffers)
-            .unwrap_or(Vec::new(&env))
+    /// Reputation: value-weighted share of good outcomes and buyer ratings blended
+    /// with a 50% prior. Declined trades count against the vendor at the configured
+    /// share of their value. Cancellations are tracked but left out, so a mutual
+    /// cancel never moves the score. Ratings only exist for settled trades, so they
+    /// carry real value.
+    fn reputation_breakdown(env: &Env, stats: &VendorStats) -> ReputationBreakdown {
+        let penalty: u32 = env.storage().instance()
+            .get(&DECL_PEN)
+            .unwrap_or(DEFAULT_DECLINE_PENALTY);
+
+        let weight = stats.positive_volume + stats.negative_volume
+            + stats.rated_volume + NEUTRAL_PRIOR_VOLUME;
+        let credit = stats.positive_volume + stats.rating_credit + NEUTRAL_PRIOR_VOLUME / 2;
+        let declined = stats.declined_volume * penalty as i128 / 100;
+        let base_score = (credit * 100 / weight) as u32;
+        let score = (credit * 100 / (weight + declined)) as u32;
+
+        ReputationBreakdown {
+            vendor: stats.vendor.clone(),
+            positive_volume: stats.positive_volume,
+            negative_volume: stats.negative_volume,
+            neutral_volume: stats.neutral_volume,
+            declined_volume: stats.declined_volume,
+            rated_volume: stats.rated_volume,
+            rating_credit: stats.rating_credit,
+            prior_volume: NEUTRAL_PRIOR_VOLUME,
+            base_score,
+            decline_penalty: base_score - score,
+            score,
+            as_of: stats.decayed_at,
+        }
     }
 
-    pub fn get_buyer_trades(env: Env, buyer: Address) -> Vec<u64> {
-        env.storage().instance()
-            .get(&P2PDataKey::BuyerTrades(buyer))
-            .unwrap_or(Vec::new(&env))
+    /// Bring weighted volumes forward to the current ledger time
+    fn decay_vendor_stats(env: &Env, stats: &mut VendorStats) {
+        let now = env.ledger().timestamp();
+        let elapsed = now.saturating_sub(stats.decayed_at);
+
+        stats.positive_volume = Self::decay(stats.positive_volume, elapsed);
+        stats.negative_volume = Self::decay(stats.negative_volume, elapsed);
+        stats.neutral_volume = Self::decay(stats.neutral_volume, elapsed);
+        stats.declined_volume = Self::decay(stats.declined_volume, elapsed);
+        stats.rated_volume = Self::decay(stats.rated_volume, elapsed);
+        stats.rating_credit = Self::decay(stats.rating_credit, elapsed);
+        stats.decayed_at = now;
     }
 
-    pub fn get_vendor_trades(env: Env, vendor: Address) -> Vec<u64> {
-        env.storage().instance()
-            .get(&P2PDataKey::VendorTrades(vendor))
-            .unwrap_or(Vec::new(&env))
+    /// Halve per REPUTATION_HALF_LIFE, interpolating linearly within a half-life
+    fn decay(value: i128, elapsed: u64) -> i128 {
+        let halvings = elapsed / REPUTATION_HALF_LIFE;
+        if halvings >= 127 {
+            return 0;
+        }
+
+        let halved = value >> halvings;
+        let rest = (elapsed % REPUTATION_HALF_LIFE) as i128;
+        halved - halved * rest / (2 * REPUTATION_HALF_LIFE as i128)
     }
 
-    pub fn get_vendor_stats(env: Env, vendor: Address) -> VendorStats {
-        env.storage().instance()
-            .get(&P2PDataKey::VendorStats(vendor.clone()))
+    fn load_vendor_stats(env: &Env, vendor: Address) -> VendorStats {
+        Self::read_entry(env, &P2PDataKey::VendorStats(vendor.clone()))
             .unwrap_or(VendorStats {
                 vendor,
                 total_trades: 0,
                 completed_trades: 0,
                 disputed_trades: 0,
+                declined_trades: 0,
+                cancelled_trades: 0,
+                fraud_trades: 0,
                 total_volume: 0,
-                reputation_score: 100,
+                positive_volume: 0,
+                negative_volume: 0,
+                neutral_volume: 0,
+                declined_volume: 0,
+                rated_volume: 0,
+                rating_credit: 0,
+                decayed_at: env.ledger().timestamp(),
+                rating_count: 0,
+                rating_average: 0,
+                reputation_score: 50,
             })
     }
 
-    // ========================================================================
-    // INTERNAL HELPERS
-    // ========================================================================
-
-    /// Transfer tokens from user to contract (user already authorized via require_auth)
-    fn transfer_from(
-        env: Env,
-        token: &Address,
-        from: &Address,
-        to: &Address,
-        amount: &i128,
-    ) {
-        use soroban_sdk::IntoVal;
-        let _: () = env.invoke_contract(
-            token,
-            &Symbol::new(&env, "transfer"),
-            (from, to, amount).into_val(&env),
-        );
-    }
-
-    /// Transfer tokens from contract to address
-    fn transfer_tokens(
-        env: Env,
-        token: &Address,
-        to: &Address,
-        amount: &i128,
-    ) {
-        use soroban_sdk::IntoVal;
-        let _: () = env.invoke_contract(
-            token,
-            &Symbol::new(&env, "transfer"),
-            (&env.current_contract_address(), to, amount).into_val(&env),
-        );
+    /// Reputation: share of started trades without a lost dispute
+    fn save_buyer_stats(env: &Env, stats: &mut BuyerStats) {
+        stats.reputation_score = ((stats.total_trades - stats.disputes_lost) * 100)
+            .checked_div(stats.total_trades)
+            .unwrap_or(100);
+        Self::write_entry(env, &P2PDataKey::BuyerStats(stats.buyer.clone()), stats);
     }
 
-    /// Update vendor statistics and reputation
-    fn update_vendor_stats(
-        env: Env,
-        vendor: Address,
-        trade_amount: i128,
-        success: bool,
-    ) {
-        let mut stats: VendorStats = env.storage().instance()
-            .get(&P2PDataKey::VendorStats(vendor.clone()))
-            .unwrap_or(VendorStats {
-                vendor: vendor.clone(),
+    fn load_buyer_stats(env: &Env, buyer: Address) -> BuyerStats {
+        Self::read_entry(env, &P2PDataKey::BuyerStats(buyer.clone()))
+            .unwrap_or(BuyerStats {
+                buyer,
                 total_trades: 0,
                 completed_trades: 0,
-                disputed_trades: 0,
+                disputes_opened: 0,
+                disputes_lost: 0,
                 total_volume: 0,
+                rating_count: 0,
+                rating_average: 0,
                 reputation_score: 100,
-            });
-
-        stats.total_trades += 1;
-        stats.total_volume += trade_amount;
-
-        if success {
-            stats.completed_trades += 1;
-        } else {
-            stats.disputed_trades += 1;
-        }
-
-        // Calculate reputation: (completed / total) * 100
-        if stats.total_trades > 0 {
-            stats.reputation_score = (stats.completed_trades * 100) / stats.total_trades;
-        }
-
-        env.storage().instance().set(&P2PDataKey::VendorStats(vendor), &stats);
+            })
     }
 }
 
@@ -1,9 +1,11 @@
 #![cfg(test)]
+extern crate std;
 
 use super::*;
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
 use soroban_sdk::{
-    testutils::Address as _,
-    token, Address, Env, String,
+    testutils::{storage::Persistent as _, Address as _, Ledger},
+    token, vec, Address, Bytes, BytesN, Env, String, Vec,
 };
 
 // Mock token contract for testing
@@ -14,11 +16,71 @@ fn create_token_contract<'a>(env: &Env, admin: &Address) -> (token::StellarAsset
     (asset_client, token_client)
 }
 
-fn create_p2p_contract(env: &Env) -> StellarP2PContractClient {
+fn create_p2p_contract(env: &Env) -> StellarP2PContractClient<'_> {
     let contract_id = env.register(StellarP2PContract, ());
     StellarP2PContractClient::new(env, &contract_id)
 }
 
+// SEP-40 style price feed with settable prices, 14 decimals like Reflector
+#[contract]
+pub struct MockOracle;
+
+#[contractimpl]
+impl MockOracle {
+    pub fn set_price(env: Env, token: Address, price: i128, timestamp: u64) {
+        env.storage().instance().set(&Asset::Stellar(token), &PriceData { price, timestamp });
+    }
+
+    pub fn lastprice(env: Env, asset: Asset) -> Option<PriceData> {
+        env.storage().instance().get(&asset)
+    }
+
+    pub fn decimals(_env: Env) -> u32 {
+        14
+    }
+}
+
+// Lending pool that can be told to run short of liquidity
+#[contract]
+pub struct MockPool;
+
+#[contractimpl]
+impl MockPool {
+    pub fn supply(env: Env, from: Address, token: Address, amount: i128) {
+        from.require_auth();
+        token::Client::new(&env, &token).transfer(&from, &env.current_contract_address(), &amount);
+        let position: i128 = env.storage().instance().get(&(from.clone(), token.clone())).unwrap_or(0);
+        env.storage().instance().set(&(from, token), &(position + amount));
+    }
+
+    pub fn withdraw(env: Env, from: Address, token: Address, amount: i128, to: Address) -> i128 {
+        from.require_auth();
+        let position: i128 = env.storage().instance().get(&(from.clone(), token.clone())).unwrap_or(0);
+        let liquidity: Option<i128> = env.storage().instance().get(&symbol_short!("liquidity"));
+        let sent = amount.min(position).min(liquidity.unwrap_or(i128::MAX));
+        token::Client::new(&env, &token).transfer(&env.current_contract_address(), &to, &sent);
+        env.storage().instance().set(&(from, token), &(position - sent));
+        sent
+    }
+
+    pub fn balance(env: Env, owner: Address, token: Address) -> i128 {
+        env.storage().instance().get(&(owner, token)).unwrap_or(0)
+    }
+
+    /// Credit interest; the test mints the matching tokens to the pool
+    pub fn accrue(env: Env, owner: Address, token: Address, interest: i128) {
+        let position: i128 = env.storage().instance().get(&(owner.clone(), token.clone())).unwrap_or(0);
+        env.storage().instance().set(&(owner, token), &(position + interest));
+    }
+
+    pub fn set_liquidity(env: Env, liquidity: Option<i128>) {
+        match liquidity {
+            Some(cap) => env.storage().instance().set(&symbol_short!("liquidity"), &cap),
+            None => env.storage().instance().remove(&symbol_short!("liquidity")),
+        }
+    }
+}
+
 #[test]
 fn test_initialize() {
     let env = Env::default();
@@ -27,13 +89,12 @@ fn test_initialize() {
     let admin = Address::generate(&env);
     let client = create_p2p_contract(&env);
 
-    client.initialize(&admin, &120, &86400, &604800);
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
 
     // Verify initialization worked (no panic)
 }
 
 #[test]
-#[should_panic(expected = "Already initialized")]
 fn test_cannot_initialize_twice() {
     let env = Env::default();
     env.mock_all_auths();
@@ -41,8 +102,11 @@ fn test_cannot_initialize_twice() {
     let admin = Address::generate(&env);
     let client = create_p2p_contract(&env);
 
-    client.initialize(&admin, &120, &86400, &604800);
-    client.initialize(&admin, &120, &86400, &604800); // Should panic
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    assert_eq!(
+        client.try_initialize(&admin, &120, &86400, &604800, &0, &admin),
+        Err(Ok(P2PError::AlreadyInitialized))
+    );
 }
 
 #[test]
@@ -55,7 +119,7 @@ fn test_register_token() {
     let client = create_p2p_contract(&env);
     let (usdc, _) = create_token_contract(&env, &token_admin);
 
-    client.initialize(&admin, &120, &86400, &604800);
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
     client.register_token(&admin, &usdc.address);
 
     // Verify token registered (no panic)
@@ -71,10 +135,10 @@ fn test_create_offer() {
     let token_admin = Address::generate(&env);
 
     let client = create_p2p_contract(&env);
-    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
 
     // Initialize contract
-    client.initialize(&admin, &120, &86400, &604800);
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
     client.register_token(&admin, &usdc.address);
 
     // Mint USDC to vendor for collateral
@@ -87,6 +151,7 @@ fn test_create_offer() {
         &String::from_str(&env, "Amazon $50 Gift Card"),
         &String::from_str(&env, "Digital code delivered via email"),
         &1000, // Price: 1000 USDC
+        &1,
         &usdc.address,
         &1200, // Collateral: 120% of price
         &usdc.address,
@@ -103,7 +168,6 @@ fn test_create_offer() {
 }
 
 #[test]
-#[should_panic(expected = "Insufficient collateral")]
 fn test_create_offer_insufficient_collateral() {
     let env = Env::default();
     env.mock_all_auths();
@@ -115,22 +179,24 @@ fn test_create_offer_insufficient_collateral() {
     let client = create_p2p_contract(&env);
     let (usdc, _) = create_token_contract(&env, &token_admin);
 
-    client.initialize(&admin, &120, &86400, &604800);
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
     client.register_token(&admin, &usdc.address);
 
     usdc.mint(&vendor, &1000);
 
     // Try to create offer with only 100% collateral (should fail, needs 120%)
-    client.create_offer(
+    let result = client.try_create_offer(
         &vendor,
         &Category::GiftCards,
         &String::from_str(&env, "Amazon $50 Gift Card"),
         &String::from_str(&env, "Digital code delivered via email"),
         &1000,
+        &1,
         &usdc.address,
         &1000, // Only 100% - should fail
         &usdc.address,
     );
+    assert_eq!(result, Err(Ok(P2PError::InsufficientCollateral)));
 }
 
 #[test]
@@ -145,7 +211,7 @@ fn test_cancel_offer() {
     let client = create_p2p_contract(&env);
     let (usdc, _) = create_token_contract(&env, &token_admin);
 
-    client.initialize(&admin, &120, &86400, &604800);
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
     client.register_token(&admin, &usdc.address);
 
     usdc.mint(&vendor, &1200);
@@ -156,6 +222,7 @@ fn test_cancel_offer() {
         &String::from_str(&env, "Amazon $50 Gift Card"),
         &String::from_str(&env, "Digital code"),
         &1000,
+        &1,
         &usdc.address,
         &1200,
         &usdc.address,
@@ -182,7 +249,7 @@ fn test_accept_offer() {
     let client = create_p2p_contract(&env);
     let (usdc, _) = create_token_contract(&env, &token_admin);
 
-    client.initialize(&admin, &120, &86400, &604800);
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
     client.register_token(&admin, &usdc.address);
 
     usdc.mint(&vendor, &1200);
@@ -194,13 +261,14 @@ fn test_accept_offer() {
         &String::from_str(&env, "Amazon $50"),
         &String::from_str(&env, "Digital code"),
         &1000,
+        &1,
         &usdc.address,
         &1200,
         &usdc.address,
     );
 
     // Buyer accepts offer
-    let trade_id = client.accept_offer(&buyer, &offer_id);
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
 
     assert_eq!(trade_id, 1);
 
@@ -217,7 +285,6 @@ fn test_accept_offer() {
 }
 
 #[test]
-#[should_panic(expected = "Cannot trade with yourself")]
 fn test_cannot_self_trade() {
     let env = Env::default();
     env.mock_all_auths();
@@ -229,7 +296,7 @@ fn test_cannot_self_trade() {
     let client = create_p2p_contract(&env);
     let (usdc, _) = create_token_contract(&env, &token_admin);
 
-    client.initialize(&admin, &120, &86400, &604800);
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
     client.register_token(&admin, &usdc.address);
 
     usdc.mint(&vendor, &2200);
@@ -240,13 +307,17 @@ fn test_cannot_self_trade() {
         &String::from_str(&env, "Amazon $50"),
         &String::from_str(&env, "Digital code"),
         &1000,
+        &1,
         &usdc.address,
         &1200,
         &usdc.address,
     );
 
     // Vendor tries to accept own offer
-    client.accept_offer(&vendor, &offer_id);
+    assert_eq!(
+        client.try_accept_offer(&vendor, &offer_id, &1, &None, &None, &None, &None),
+        Err(Ok(P2PError::SelfTrade))
+    );
 }
 
 #[test]
@@ -262,7 +333,7 @@ fn test_mark_delivered() {
     let client = create_p2p_contract(&env);
     let (usdc, _) = create_token_contract(&env, &token_admin);
 
-    client.initialize(&admin, &120, &86400, &604800);
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
     client.register_token(&admin, &usdc.address);
 
     usdc.mint(&vendor, &1200);
@@ -274,12 +345,13 @@ fn test_mark_delivered() {
         &String::from_str(&env, "Amazon $50"),
         &String::from_str(&env, "Digital code"),
         &1000,
+        &1,
         &usdc.address,
         &1200,
         &usdc.address,
     );
 
-    let trade_id = client.accept_offer(&buyer, &offer_id);
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
 
     // Vendor marks as delivered
     client.mark_delivered(
@@ -307,7 +379,7 @@ fn test_confirm_delivery() {
     let client = create_p2p_contract(&env);
     let (usdc, _) = create_token_contract(&env, &token_admin);
 
-    client.initialize(&admin, &120, &86400, &604800);
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
     client.register_token(&admin, &usdc.address);
 
     usdc.mint(&vendor, &1200);
@@ -319,12 +391,13 @@ fn test_confirm_delivery() {
         &String::from_str(&env, "Amazon $50"),
         &String::from_str(&env, "Digital code"),
         &1000,
+        &1,
         &usdc.address,
         &1200,
         &usdc.address,
     );
 
-    let trade_id = client.accept_offer(&buyer, &offer_id);
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
 
     client.mark_delivered(
         &vendor,
@@ -349,7 +422,8 @@ fn test_confirm_delivery() {
     assert_eq!(stats.total_trades, 1);
     assert_eq!(stats.completed_trades, 1);
     assert_eq!(stats.disputed_trades, 0);
-    assert_eq!(stats.reputation_score, 100);
+    // One small trade barely moves the neutral prior
+    assert_eq!(stats.reputation_score, 50);
 }
 
 #[test]
@@ -365,7 +439,7 @@ fn test_dispute_trade() {
     let client = create_p2p_contract(&env);
     let (usdc, _) = create_token_contract(&env, &token_admin);
 
-    client.initialize(&admin, &120, &86400, &604800);
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
     client.register_token(&admin, &usdc.address);
 
     usdc.mint(&vendor, &1200);
@@ -377,12 +451,13 @@ fn test_dispute_trade() {
         &String::from_str(&env, "Amazon $50"),
         &String::from_str(&env, "Digital code"),
         &1000,
+        &1,
         &usdc.address,
         &1200,
         &usdc.address,
     );
 
-    let trade_id = client.accept_offer(&buyer, &offer_id);
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
 
     client.mark_delivered(
         &vendor,
@@ -397,16 +472,24 @@ fn test_dispute_trade() {
         &String::from_str(&env, "Code already used"),
     );
 
-    // Verify trade disputed
+    // Verify trade disputed, funds still in escrow
     let trade = client.get_trade(&trade_id);
     assert_eq!(trade.status, TradeStatus::DisputedBuyer);
+    assert_eq!(client.get_vendor_stats(&vendor).total_trades, 0);
+
+    // Vendor never responds, buyer wins by default
+    env.ledger().with_mut(|li| li.timestamp += 604801);
+    client.claim_default_ruling(&trade_id);
+
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.status, TradeStatus::Resolved);
 
     // Verify vendor stats updated (negative)
     let stats = client.get_vendor_stats(&vendor);
     assert_eq!(stats.total_trades, 1);
     assert_eq!(stats.completed_trades, 0);
     assert_eq!(stats.disputed_trades, 1);
-    assert_eq!(stats.reputation_score, 0);
+    assert_eq!(stats.reputation_score, 49);
 }
 
 #[test]
@@ -421,7 +504,7 @@ fn test_get_vendor_offers() {
     let client = create_p2p_contract(&env);
     let (usdc, _) = create_token_contract(&env, &token_admin);
 
-    client.initialize(&admin, &120, &86400, &604800);
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
     client.register_token(&admin, &usdc.address);
 
     usdc.mint(&vendor, &3600);
@@ -433,6 +516,7 @@ fn test_get_vendor_offers() {
         &String::from_str(&env, "Amazon $50"),
         &String::from_str(&env, "Digital code"),
         &1000,
+        &1,
         &usdc.address,
         &1200,
         &usdc.address,
@@ -444,6 +528,7 @@ fn test_get_vendor_offers() {
         &String::from_str(&env, "PUBG UC"),
         &String::from_str(&env, "Game top-up"),
         &500,
+        &1,
         &usdc.address,
         &600,
         &usdc.address,
@@ -468,7 +553,7 @@ fn test_get_category_offers() {
     let client = create_p2p_contract(&env);
     let (usdc, _) = create_token_contract(&env, &token_admin);
 
-    client.initialize(&admin, &120, &86400, &604800);
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
     client.register_token(&admin, &usdc.address);
 
     usdc.mint(&vendor, &3600);
@@ -480,6 +565,7 @@ fn test_get_category_offers() {
         &String::from_str(&env, "Amazon $50"),
         &String::from_str(&env, "Digital code"),
         &1000,
+        &1,
         &usdc.address,
         &1200,
         &usdc.address,
@@ -491,6 +577,7 @@ fn test_get_category_offers() {
         &String::from_str(&env, "PUBG UC"),
         &String::from_str(&env, "Game top-up"),
         &500,
+        &1,
         &usdc.address,
         &600,
         &usdc.address,
@@ -508,7 +595,6 @@ fn test_get_category_offers() {
 }
 
 #[test]
-#[should_panic(expected = "Has active trades")]
 fn test_cannot_cancel_offer_with_active_trades() {
     let env = Env::default();
     env.mock_all_auths();
@@ -521,7 +607,7 @@ fn test_cannot_cancel_offer_with_active_trades() {
     let client = create_p2p_contract(&env);
     let (usdc, _) = create_token_contract(&env, &token_admin);
 
-    client.initialize(&admin, &120, &86400, &604800);
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
     client.register_token(&admin, &usdc.address);
 
     usdc.mint(&vendor, &1200);
@@ -533,14 +619,3171 @@ fn test_cannot_cancel_offer_with_active_trades() {
         &String::from_str(&env, "Amazon $50"),
         &String::from_str(&env, "Digital code"),
         &1000,
+        &1,
         &usdc.address,
         &1200,
         &usdc.address,
     );
 
     // Create active trade
-    client.accept_offer(&buyer, &offer_id);
+    client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
 
     // Try to cancel (should fail)
+    assert_eq!(
+        client.try_cancel_offer(&vendor, &offer_id),
+        Err(Ok(P2PError::HasActiveTrades))
+    );
+}
+
+#[test]
+fn test_accept_offer_reserves_collateral() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &3000);
+    usdc.mint(&buyer, &1000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &1,
+        &usdc.address,
+        &3000,
+        &usdc.address,
+    );
+
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+
+    // Only the 120% slice is reserved, the rest stays free
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.collateral_amount, 1200);
+
+    let offer = client.get_offer(&offer_id);
+    assert_eq!(offer.collateral.amount, 3000);
+    assert_eq!(offer.collateral.reserved, 1200);
+    assert_eq!(client.get_free_collateral(&offer_id), 1800);
+}
+
+#[test]
+fn test_cannot_accept_sold_out_offer() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer1 = Address::generate(&env);
+    let buyer2 = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &1200);
+    usdc.mint(&buyer1, &1000);
+    usdc.mint(&buyer2, &1000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &1,
+        &usdc.address,
+        &1200,
+        &usdc.address,
+    );
+
+    client.accept_offer(&buyer1, &offer_id, &1, &None, &None, &None, &None);
+
+    // Single unit is gone
+    let offer = client.get_offer(&offer_id);
+    assert_eq!(offer.status, OfferStatus::SoldOut);
+    assert_eq!(client.get_active_offers().len(), 0);
+
+    assert_eq!(
+        client.try_accept_offer(&buyer2, &offer_id, &1, &None, &None, &None, &None),
+        Err(Ok(P2PError::OfferNotActive))
+    );
+}
+
+#[test]
+fn test_dispute_only_pays_trade_reservation() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer1 = Address::generate(&env);
+    let buyer2 = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &2400);
+    usdc.mint(&buyer1, &1000);
+    usdc.mint(&buyer2, &1000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &2,
+        &usdc.address,
+        &2400,
+        &usdc.address,
+    );
+
+    let trade1 = client.accept_offer(&buyer1, &offer_id, &1, &None, &None, &None, &None);
+    let trade2 = client.accept_offer(&buyer2, &offer_id, &1, &None, &None, &None, &None);
+
+    client.mark_delivered(&vendor, &trade1, &String::from_str(&env, "CODE: INVALID"));
+    client.mark_delivered(&vendor, &trade2, &String::from_str(&env, "CODE: XXXX"));
+
+    let arbiter = Address::generate(&env);
+    client.register_arbiter(&admin, &arbiter);
+
+    client.dispute_trade(&buyer1, &trade1, &String::from_str(&env, "Code already used"));
+    client.resolve_dispute(&arbiter, &trade1, &10000, &10000);
+    client.confirm_delivery(&buyer2, &trade2);
+
+    // Disputing buyer gets their payment back plus one reservation
+    assert_eq!(usdc_token.balance(&buyer1), 2200);
+
+    // The completed trade's reservation is released, not paid out twice
+    let offer = client.get_offer(&offer_id);
+    assert_eq!(offer.collateral.amount, 1200);
+    assert_eq!(offer.collateral.reserved, 0);
+
     client.cancel_offer(&vendor, &offer_id);
+    assert_eq!(usdc_token.balance(&vendor), 1000 + 1200);
+    assert_eq!(usdc_token.balance(&client.address), 0);
+}
+
+#[test]
+fn test_counter_dispute_and_arbiter_split() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let arbiter = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+    client.register_arbiter(&admin, &arbiter);
+
+    usdc.mint(&vendor, &1200);
+    usdc.mint(&buyer, &1000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &1,
+        &usdc.address,
+        &1200,
+        &usdc.address,
+    );
+
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+    client.mark_delivered(&vendor, &trade_id, &String::from_str(&env, "CODE: XXXX"));
+    client.dispute_trade(&buyer, &trade_id, &String::from_str(&env, "Code already used"));
+
+    client.counter_dispute(&vendor, &trade_id, &String::from_str(&env, "Redemption screenshot"));
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.status, TradeStatus::DisputedVendor);
+    assert_eq!(trade.vendor_evidence, String::from_str(&env, "Redemption screenshot"));
+
+    // Half refund, quarter of the reservation slashed
+    client.resolve_dispute(&arbiter, &trade_id, &5000, &2500);
+
+    assert_eq!(usdc_token.balance(&buyer), 500 + 300);
+    assert_eq!(usdc_token.balance(&vendor), 500);
+
+    let offer = client.get_offer(&offer_id);
+    assert_eq!(offer.collateral.amount, 900);
+    assert_eq!(offer.collateral.reserved, 0);
+    assert_eq!(offer.active_trade_count, 0);
+
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.status, TradeStatus::Resolved);
+
+    let stats = client.get_vendor_stats(&vendor);
+    assert_eq!(stats.disputed_trades, 1);
+}
+
+#[test]
+fn test_counter_dispute_after_window() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &1200);
+    usdc.mint(&buyer, &1000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &1,
+        &usdc.address,
+        &1200,
+        &usdc.address,
+    );
+
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+    client.mark_delivered(&vendor, &trade_id, &String::from_str(&env, "CODE: XXXX"));
+    client.dispute_trade(&buyer, &trade_id, &String::from_str(&env, "Code already used"));
+
+    env.ledger().with_mut(|li| li.timestamp += 604801);
+    assert_eq!(
+        client.try_counter_dispute(&vendor, &trade_id, &String::from_str(&env, "Too late")),
+        Err(Ok(P2PError::DisputeWindowClosed))
+    );
+}
+
+#[test]
+fn test_default_ruling_before_window() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &1200);
+    usdc.mint(&buyer, &1000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &1,
+        &usdc.address,
+        &1200,
+        &usdc.address,
+    );
+
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+    client.mark_delivered(&vendor, &trade_id, &String::from_str(&env, "CODE: XXXX"));
+    client.dispute_trade(&buyer, &trade_id, &String::from_str(&env, "Code already used"));
+
+    assert_eq!(
+        client.try_claim_default_ruling(&trade_id),
+        Err(Ok(P2PError::DisputeWindowOpen))
+    );
+}
+
+#[test]
+fn test_resolve_dispute_requires_arbiter() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &1200);
+    usdc.mint(&buyer, &1000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &1,
+        &usdc.address,
+        &1200,
+        &usdc.address,
+    );
+
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+    client.mark_delivered(&vendor, &trade_id, &String::from_str(&env, "CODE: XXXX"));
+    client.dispute_trade(&buyer, &trade_id, &String::from_str(&env, "Code already used"));
+
+    // Buyer cannot rule on their own dispute
+    assert_eq!(
+        client.try_resolve_dispute(&buyer, &trade_id, &10000, &10000),
+        Err(Ok(P2PError::NotArbiter))
+    );
+}
+
+#[test]
+fn test_claim_auto_complete() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &1200);
+    usdc.mint(&buyer, &1000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &1,
+        &usdc.address,
+        &1200,
+        &usdc.address,
+    );
+
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+
+    // Window restarts from delivery
+    env.ledger().with_mut(|li| li.timestamp += 1000);
+    client.mark_delivered(&vendor, &trade_id, &String::from_str(&env, "CODE: XXXX"));
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.auto_complete_at, trade.delivered_at + 86400);
+
+    // Buyer walks away
+    env.ledger().with_mut(|li| li.timestamp += 86401);
+    client.claim_auto_complete(&trade_id);
+
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.status, TradeStatus::Completed);
+    assert_eq!(usdc_token.balance(&vendor), 1000);
+
+    let offer = client.get_offer(&offer_id);
+    assert_eq!(offer.collateral.reserved, 0);
+    assert_eq!(offer.completed_trade_count, 1);
+
+    let stats = client.get_vendor_stats(&vendor);
+    assert_eq!(stats.completed_trades, 1);
+}
+
+#[test]
+fn test_claim_auto_complete_too_early() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &1200);
+    usdc.mint(&buyer, &1000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &1,
+        &usdc.address,
+        &1200,
+        &usdc.address,
+    );
+
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+    client.mark_delivered(&vendor, &trade_id, &String::from_str(&env, "CODE: XXXX"));
+
+    assert_eq!(
+        client.try_claim_auto_complete(&trade_id),
+        Err(Ok(P2PError::AutoCompleteWindowOpen))
+    );
+}
+
+#[test]
+fn test_cannot_dispute_after_auto_complete_window() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &1200);
+    usdc.mint(&buyer, &1000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &1,
+        &usdc.address,
+        &1200,
+        &usdc.address,
+    );
+
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+    client.mark_delivered(&vendor, &trade_id, &String::from_str(&env, "CODE: XXXX"));
+
+    env.ledger().with_mut(|li| li.timestamp += 86401);
+    assert_eq!(
+        client.try_dispute_trade(&buyer, &trade_id, &String::from_str(&env, "Too late")),
+        Err(Ok(P2PError::CannotDispute))
+    );
+}
+
+#[test]
+fn test_settle_expired() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &3600);
+    usdc.mint(&buyer, &3000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &3,
+        &usdc.address,
+        &3600,
+        &usdc.address,
+    );
+
+    let trade1 = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+    let trade2 = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+    let trade3 = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+
+    client.mark_delivered(&vendor, &trade1, &String::from_str(&env, "CODE: 1"));
+    client.mark_delivered(&vendor, &trade2, &String::from_str(&env, "CODE: 2"));
+
+    // Third delivery is still inside its window at sweep time
+    env.ledger().with_mut(|li| li.timestamp += 50000);
+    client.mark_delivered(&vendor, &trade3, &String::from_str(&env, "CODE: 3"));
+
+    // Nothing due yet: each call only inspects `limit` trades and hands back a cursor
+    assert_eq!(client.settle_expired(&0, &2), (0, 2));
+    assert_eq!(client.settle_expired(&2, &2), (0, 0));
+
+    env.ledger().with_mut(|li| li.timestamp += 40000);
+
+    assert_eq!(client.settle_expired(&0, &1), (1, 0));
+    assert_eq!(client.settle_expired(&0, &10), (1, 0));
+    assert_eq!(client.settle_expired(&0, &10), (0, 0));
+
+    assert_eq!(client.get_trade(&trade1).status, TradeStatus::Completed);
+    assert_eq!(client.get_trade(&trade2).status, TradeStatus::Completed);
+    assert_eq!(client.get_trade(&trade3).status, TradeStatus::Delivered);
+    assert_eq!(usdc_token.balance(&vendor), 2000);
+}
+
+#[test]
+fn test_pause_and_resume_offer() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &2400);
+    usdc.mint(&buyer, &1000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &2,
+        &usdc.address,
+        &2400,
+        &usdc.address,
+    );
+
+    // Funded trade survives the pause
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+
+    client.pause_offer(&vendor, &offer_id);
+    assert_eq!(client.get_offer(&offer_id).status, OfferStatus::Paused);
+    assert_eq!(client.get_active_offers().len(), 0);
+    assert_eq!(client.get_category_offers(&Category::GiftCards).len(), 0);
+
+    client.mark_delivered(&vendor, &trade_id, &String::from_str(&env, "CODE: XXXX"));
+    client.confirm_delivery(&buyer, &trade_id);
+
+    client.resume_offer(&vendor, &offer_id);
+    assert_eq!(client.get_offer(&offer_id).status, OfferStatus::Active);
+    assert_eq!(client.get_active_offers().len(), 1);
+    assert_eq!(client.get_category_offers(&Category::GiftCards).len(), 1);
+
+    // Cancelling drops it from the category index too
+    client.cancel_offer(&vendor, &offer_id);
+    assert_eq!(client.get_active_offers().len(), 0);
+    assert_eq!(client.get_category_offers(&Category::GiftCards).len(), 0);
+}
+
+#[test]
+fn test_cannot_accept_paused_offer() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &1200);
+    usdc.mint(&buyer, &1000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &1,
+        &usdc.address,
+        &1200,
+        &usdc.address,
+    );
+
+    client.pause_offer(&vendor, &offer_id);
+    assert_eq!(
+        client.try_accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None),
+        Err(Ok(P2PError::OfferNotActive))
+    );
+}
+
+#[test]
+fn test_update_offer_keeps_funded_trade_terms() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &3600);
+    usdc.mint(&buyer, &1000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &2,
+        &usdc.address,
+        &3600,
+        &usdc.address,
+    );
+
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+
+    client.update_offer(
+        &vendor,
+        &offer_id,
+        &String::from_str(&env, "Amazon $100"),
+        &String::from_str(&env, "Digital code, emailed"),
+        &2000,
+    );
+
+    let offer = client.get_offer(&offer_id);
+    assert_eq!(offer.title, String::from_str(&env, "Amazon $100"));
+    assert_eq!(offer.price, 2000);
+
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.amount, 1000);
+    assert_eq!(trade.collateral_amount, 1200);
+}
+
+#[test]
+fn test_update_offer_price_increase_needs_collateral() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &1200);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &1,
+        &usdc.address,
+        &1200,
+        &usdc.address,
+    );
+
+    let result = client.try_update_offer(
+        &vendor,
+        &offer_id,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1100,
+    );
+    assert_eq!(result, Err(Ok(P2PError::InsufficientCollateral)));
+}
+
+#[test]
+fn test_add_and_withdraw_collateral() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer1 = Address::generate(&env);
+    let buyer2 = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &3000);
+    usdc.mint(&buyer1, &1000);
+    usdc.mint(&buyer2, &1000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &1,
+        &usdc.address,
+        &1200,
+        &usdc.address,
+    );
+
+    client.accept_offer(&buyer1, &offer_id, &1, &None, &None, &None, &None);
+
+    // Over-collateralise, then restock a unit with its matching collateral
+    client.add_collateral(&vendor, &offer_id, &600);
+    client.restock_offer(&vendor, &offer_id, &1);
+    assert_eq!(client.get_offer(&offer_id).collateral.amount, 3000);
+    client.accept_offer(&buyer2, &offer_id, &1, &None, &None, &None, &None);
+    assert_eq!(client.get_free_collateral(&offer_id), 600);
+
+    // Only the unreserved part can leave
+    client.withdraw_free_collateral(&vendor, &offer_id, &600);
+
+    let offer = client.get_offer(&offer_id);
+    assert_eq!(offer.collateral.amount, 2400);
+    assert_eq!(offer.collateral.reserved, 2400);
+    assert_eq!(usdc_token.balance(&vendor), 600);
+}
+
+#[test]
+fn test_cannot_withdraw_reserved_collateral() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &2000);
+    usdc.mint(&buyer, &1000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &1,
+        &usdc.address,
+        &2000,
+        &usdc.address,
+    );
+
+    client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+
+    // 1200 is reserved, only 800 free
+    assert_eq!(
+        client.try_withdraw_free_collateral(&vendor, &offer_id, &900),
+        Err(Ok(P2PError::InsufficientFreeCollateral))
+    );
+}
+
+#[test]
+fn test_accept_multiple_units() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &24000);
+    usdc.mint(&buyer, &5000);
+
+    // 20 x $50 codes, collateral covers every unit
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &20,
+        &usdc.address,
+        &24000,
+        &usdc.address,
+    );
+
+    let trade_id = client.accept_offer(&buyer, &offer_id, &5, &None, &None, &None, &None);
+
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.units, 5);
+    assert_eq!(trade.amount, 5000);
+    assert_eq!(trade.collateral_amount, 6000);
+    assert_eq!(usdc_token.balance(&buyer), 0);
+
+    let offer = client.get_offer(&offer_id);
+    assert_eq!(offer.quantity_total, 20);
+    assert_eq!(offer.quantity_remaining, 15);
+    assert_eq!(offer.collateral.reserved, 6000);
+    assert_eq!(offer.status, OfferStatus::Active);
+}
+
+#[test]
+fn test_create_offer_collateral_scales_with_quantity() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &2400);
+
+    // Enough for two units, not three
+    let result = client.try_create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &3,
+        &usdc.address,
+        &2400,
+        &usdc.address,
+    );
+    assert_eq!(result, Err(Ok(P2PError::InsufficientCollateral)));
+}
+
+#[test]
+fn test_restock_sold_out_offer() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &6000);
+    usdc.mint(&buyer, &2000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &2,
+        &usdc.address,
+        &2400,
+        &usdc.address,
+    );
+
+    client.accept_offer(&buyer, &offer_id, &2, &None, &None, &None, &None);
+    assert_eq!(client.get_offer(&offer_id).status, OfferStatus::SoldOut);
+    assert_eq!(client.get_category_offers(&Category::GiftCards).len(), 0);
+
+    client.restock_offer(&vendor, &offer_id, &3);
+
+    let offer = client.get_offer(&offer_id);
+    assert_eq!(offer.status, OfferStatus::Active);
+    assert_eq!(offer.quantity_total, 5);
+    assert_eq!(offer.quantity_remaining, 3);
+    assert_eq!(offer.collateral.amount, 6000);
+    assert_eq!(usdc_token.balance(&vendor), 0);
+    assert_eq!(client.get_active_offers().len(), 1);
+}
+
+#[test]
+fn test_mutual_cancel() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &1200);
+    usdc.mint(&buyer, &1000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &1,
+        &usdc.address,
+        &1200,
+        &usdc.address,
+    );
+
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+
+    client.request_cancel(&buyer, &trade_id);
+    assert_eq!(client.get_trade(&trade_id).cancel_requested_by, Some(buyer.clone()));
+
+    client.approve_cancel(&vendor, &trade_id);
+
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.status, TradeStatus::Cancelled);
+    assert_eq!(usdc_token.balance(&buyer), 1000);
+
+    // Collateral released, unit back on sale
+    let offer = client.get_offer(&offer_id);
+    assert_eq!(offer.collateral.amount, 1200);
+    assert_eq!(offer.collateral.reserved, 0);
+    assert_eq!(offer.quantity_remaining, 1);
+    assert_eq!(offer.status, OfferStatus::Active);
+
+    // No reputation hit
+    let stats = client.get_vendor_stats(&vendor);
+    assert_eq!(stats.total_trades, 0);
+    assert_eq!(stats.cancelled_trades, 1);
+    assert_eq!(stats.reputation_score, 50);
+}
+
+#[test]
+fn test_mutual_cancel_keeps_earned_reputation() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    let price = 1000_0000000i128;
+    usdc.mint(&vendor, &(price * 2 * 2));
+    usdc.mint(&buyer, &(price * 2));
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $1000"),
+        &String::from_str(&env, "Digital code"),
+        &price,
+        &2,
+        &usdc.address,
+        &(price * 2 * 2),
+        &usdc.address,
+    );
+
+    let completed = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+    client.mark_delivered(&vendor, &completed, &String::from_str(&env, "CODE: 1"));
+    client.confirm_delivery(&buyer, &completed);
+    let earned = client.get_reputation(&vendor).score;
+    assert!(earned > 50);
+
+    // A cancel as large as the vendor's whole history doesn't pull the score back
+    let cancelled = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+    client.request_cancel(&vendor, &cancelled);
+    client.approve_cancel(&buyer, &cancelled);
+
+    let rep = client.get_reputation(&vendor);
+    assert_eq!(rep.neutral_volume, price);
+    assert_eq!(rep.score, earned);
+    assert_eq!(client.get_vendor_stats(&vendor).reputation_score, earned);
+}
+
+#[test]
+fn test_cannot_approve_own_cancel() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &1200);
+    usdc.mint(&buyer, &1000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &1,
+        &usdc.address,
+        &1200,
+        &usdc.address,
+    );
+
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+
+    client.request_cancel(&buyer, &trade_id);
+    assert_eq!(
+        client.try_approve_cancel(&buyer, &trade_id),
+        Err(Ok(P2PError::CannotApproveOwnRequest))
+    );
+}
+
+#[test]
+fn test_buyer_cancels_after_missed_delivery() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &1200);
+    usdc.mint(&buyer, &1000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &1,
+        &usdc.address,
+        &1200,
+        &usdc.address,
+    );
+
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+
+    // Vendor never delivers; no approval needed
+    env.ledger().with_mut(|li| li.timestamp += 86401);
+    client.request_cancel(&buyer, &trade_id);
+
+    assert_eq!(client.get_trade(&trade_id).status, TradeStatus::Cancelled);
+    assert_eq!(usdc_token.balance(&buyer), 1000);
+}
+
+#[test]
+fn test_decline_trade() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+    // A declined trade counts against the vendor at half its value
+    client.set_decline_penalty(&admin, &50);
+
+    usdc.mint(&vendor, &600_0000000);
+    usdc.mint(&buyer, &500_0000000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &500_0000000,
+        &1,
+        &usdc.address,
+        &600_0000000,
+        &usdc.address,
+    );
+
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+    client.decline_trade(&vendor, &trade_id);
+
+    assert_eq!(client.get_trade(&trade_id).status, TradeStatus::Cancelled);
+    assert_eq!(usdc_token.balance(&buyer), 500_0000000);
+    assert_eq!(client.get_offer(&offer_id).collateral.reserved, 0);
+
+    let stats = client.get_vendor_stats(&vendor);
+    assert_eq!(stats.declined_trades, 1);
+    assert_eq!(stats.declined_volume, 500_0000000);
+    // 250 of credit over the 500 prior plus 250 declined
+    assert_eq!(stats.reputation_score, 33);
+
+    // Declines fade like any other outcome
+    env.ledger().with_mut(|li| li.timestamp += REPUTATION_HALF_LIFE);
+    let rep = client.get_reputation(&vendor);
+    assert_eq!(rep.declined_volume, 250_0000000);
+    assert_eq!(rep.base_score, 50);
+    assert_eq!(rep.decline_penalty, 10);
+    assert_eq!(rep.score, 40);
+}
+
+#[test]
+fn test_ruling_weighs_reputation_by_refund_share() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let arbiter = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+    client.register_arbiter(&admin, &arbiter);
+
+    usdc.mint(&vendor, &360_0000000);
+    usdc.mint(&buyer, &300_0000000);
+
+    let price = 100_0000000i128;
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $100"),
+        &String::from_str(&env, "Digital code"),
+        &price,
+        &3,
+        &usdc.address,
+        &360_0000000,
+        &usdc.address,
+    );
+
+    // Full refund without a slash, a ruling for the vendor, and a split
+    for buyer_share in [10000u32, 0, 5000] {
+        let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+        client.mark_delivered(&vendor, &trade_id, &String::from_str(&env, "CODE"));
+        client.dispute_trade(&buyer, &trade_id, &String::from_str(&env, "Code invalid"));
+        client.resolve_dispute(&arbiter, &trade_id, &buyer_share, &0);
+    }
+
+    let stats = client.get_vendor_stats(&vendor);
+    assert_eq!(stats.completed_trades, 1);
+    assert_eq!(stats.disputed_trades, 2);
+    assert_eq!(stats.fraud_trades, 2);
+
+    // Only the refunded share counts against the vendor, fraud-weighted
+    let rep = client.get_reputation(&vendor);
+    assert_eq!(rep.positive_volume, price + price / 2);
+    assert_eq!(rep.negative_volume, 3 * price + 3 * price / 2);
+}
+
+#[test]
+fn test_protocol_fee_on_completion() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+
+    // 2.5% fee
+    client.initialize(&admin, &120, &86400, &604800, &250, &treasury);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &2398);
+    usdc.mint(&buyer, &1998);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &999,
+        &2,
+        &usdc.address,
+        &2398,
+        &usdc.address,
+    );
+
+    // Confirmed by buyer
+    let trade1 = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+    client.mark_delivered(&vendor, &trade1, &String::from_str(&env, "CODE: 1"));
+    client.confirm_delivery(&buyer, &trade1);
+
+    // Auto-completed after the window
+    let trade2 = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+    client.mark_delivered(&vendor, &trade2, &String::from_str(&env, "CODE: 2"));
+    env.ledger().with_mut(|li| li.timestamp += 86401);
+    client.claim_auto_complete(&trade2);
+
+    // 999 * 2.5% = 24.975, fee rounds down and the vendor keeps the rest
+    assert_eq!(usdc_token.balance(&vendor), 975 * 2);
+    assert_eq!(client.get_fee_balance(&usdc.address), 24 * 2);
+
+    let withdrawn = client.withdraw_fees(&admin, &usdc.address);
+    assert_eq!(withdrawn, 48);
+    assert_eq!(usdc_token.balance(&treasury), 48);
+    assert_eq!(client.get_fee_balance(&usdc.address), 0);
+
+    // Every unit of payment is accounted for
+    client.cancel_offer(&vendor, &offer_id);
+    assert_eq!(usdc_token.balance(&vendor) + usdc_token.balance(&treasury), 1998 + 2398);
+    assert_eq!(usdc_token.balance(&client.address), 0);
+}
+
+#[test]
+fn test_fee_cap() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let client = create_p2p_contract(&env);
+
+    assert_eq!(
+        client.try_initialize(&admin, &120, &86400, &604800, &1001, &admin),
+        Err(Ok(P2PError::FeeTooHigh))
+    );
+}
+
+#[test]
+fn test_offers_and_trades_use_persistent_storage() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &1200);
+    usdc.mint(&buyer, &1000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &1,
+        &usdc.address,
+        &1200,
+        &usdc.address,
+    );
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+
+    env.as_contract(&client.address, || {
+        let offer_key = P2PDataKey::Offer(offer_id);
+        let trade_key = P2PDataKey::Trade(trade_id);
+        let index_key = P2PDataKey::BuyerTrades(buyer.clone());
+
+        assert!(!env.storage().instance().has(&offer_key));
+        assert!(!env.storage().instance().has(&trade_key));
+        assert_eq!(env.storage().persistent().get_ttl(&offer_key), OFFER_TTL_EXTEND);
+        assert_eq!(env.storage().persistent().get_ttl(&trade_key), TRADE_TTL_EXTEND);
+        assert_eq!(env.storage().persistent().get_ttl(&index_key), INDEX_TTL_EXTEND);
+    });
+}
+
+#[test]
+fn test_bump_offer_extends_ttl() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &1200);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &1,
+        &usdc.address,
+        &1200,
+        &usdc.address,
+    );
+
+    // Let the offer drift below its extension threshold
+    env.ledger().with_mut(|li| li.sequence_number += 31 * DAY_IN_LEDGERS);
+    let key = P2PDataKey::Offer(offer_id);
+    env.as_contract(&client.address, || {
+        assert_eq!(env.storage().persistent().get_ttl(&key), 29 * DAY_IN_LEDGERS);
+    });
+
+    client.bump_offer(&offer_id);
+
+    env.as_contract(&client.address, || {
+        assert_eq!(env.storage().persistent().get_ttl(&key), OFFER_TTL_EXTEND);
+    });
+    assert_eq!(client.try_bump_trade(&1), Err(Ok(P2PError::TradeNotFound)));
+}
+
+#[test]
+fn test_migrate_storage_from_instance() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+
+    // Escrow the original build would be holding for one funded trade
+    usdc.mint(&client.address, &(1200 + 1000));
+
+    // Seed the original build's instance-storage layout
+    let offer = LegacyOffer {
+        id: 1,
+        vendor: vendor.clone(),
+        category: Category::GiftCards,
+        title: String::from_str(&env, "Amazon $50"),
+        description: String::from_str(&env, "Digital code"),
+        price: 1000,
+        payment_token: usdc.address.clone(),
+        collateral: LegacyCollateralInfo {
+            amount: 1200,
+            token_type: LegacyTokenType::USDC,
+            token_address: usdc.address.clone(),
+            locked_at: 0,
+            yield_accrued: 0,
+        },
+        status: OfferStatus::Active,
+        active_trade_count: 1,
+        completed_trade_count: 0,
+        created_at: 0,
+        updated_at: 0,
+    };
+    let trade = LegacyTrade {
+        id: 1,
+        offer_id: 1,
+        buyer: buyer.clone(),
+        vendor: vendor.clone(),
+        amount: 1000,
+        payment_token: usdc.address.clone(),
+        collateral_amount: 1200,
+        status: TradeStatus::Funded,
+        delivery_proof: String::from_str(&env, ""),
+        created_at: 0,
+        delivered_at: 0,
+        completed_at: 0,
+        dispute_reason: String::from_str(&env, ""),
+        auto_complete_at: 86400,
+    };
+    let stats = LegacyVendorStats {
+        vendor: vendor.clone(),
+        total_trades: 4,
+        completed_trades: 3,
+        disputed_trades: 1,
+        total_volume: 3000,
+        reputation_score: 75,
+    };
+    let ids: Vec<u64> = vec![&env, 1];
+    let index_keys = [
+        P2PDataKey::VendorOffers(vendor.clone()),
+        P2PDataKey::CategoryOffers(Category::GiftCards),
+        P2PDataKey::ActiveOffers,
+        P2PDataKey::OfferTrades(1),
+        P2PDataKey::BuyerTrades(buyer.clone()),
+        P2PDataKey::VendorTrades(vendor.clone()),
+    ];
+    env.as_contract(&client.address, || {
+        let storage = env.storage().instance();
+        storage.set(&P2PDataKey::Offer(1), &offer);
+        storage.set(&P2PDataKey::Trade(1), &trade);
+        storage.set(&P2PDataKey::VendorStats(vendor.clone()), &stats);
+        storage.set(&P2PDataKey::SupportedToken(usdc.address.clone()), &true);
+        storage.set(&OFFER_CNT, &1u64);
+        storage.set(&TRADE_CNT, &1u64);
+        for key in index_keys.iter() {
+            storage.set(key, &ids);
+        }
+    });
+
+    assert_eq!(client.get_supported_tokens().len(), 0);
+
+    assert_eq!(client.migrate_storage(&admin, &1), 1);
+    assert_eq!(client.migrate_storage(&admin, &10), 1);
+    assert_eq!(client.migrate_storage(&admin, &10), 0);
+
+    env.as_contract(&client.address, || {
+        for key in index_keys.iter().chain([
+            P2PDataKey::Offer(1),
+            P2PDataKey::Trade(1),
+            P2PDataKey::VendorStats(vendor.clone()),
+        ].iter()) {
+            assert!(env.storage().persistent().has(key));
+            assert!(!env.storage().instance().has(key));
+        }
+    });
+
+    // Converted to the current layout, with the trade reserving the collateral
+    let migrated = client.get_offer(&1);
+    assert_eq!(migrated.quantity_remaining, 1);
+    assert_eq!(migrated.collateral.reserved, 1200);
+    assert_eq!(migrated.collateral.token_symbol, usdc_token.symbol());
+    assert_eq!(client.get_trade(&1).units, 1);
+    assert_eq!(client.get_supported_tokens().get(0).unwrap().token, usdc.address);
+
+    let vendor_stats = client.get_vendor_stats(&vendor);
+    assert_eq!(vendor_stats.total_trades, 4);
+    assert_eq!(vendor_stats.total_volume, 3000);
+    assert_eq!(vendor_stats.reputation_score, 50);
+
+    // Trade continues normally on migrated data
+    client.mark_delivered(&vendor, &1, &String::from_str(&env, "CODE: 1"));
+    client.confirm_delivery(&buyer, &1);
+    assert_eq!(usdc_token.balance(&vendor), 1000);
+    assert_eq!(client.get_active_offers().len(), 1);
+
+    client.cancel_offer(&vendor, &1);
+    assert_eq!(usdc_token.balance(&vendor), 2200);
+    assert_eq!(usdc_token.balance(&client.address), 0);
+
+    assert_eq!(
+        client.try_upgrade(&buyer, &BytesN::from_array(&env, &[0u8; 32])),
+        Err(Ok(P2PError::NotAdmin))
+    );
+}
+
+fn no_filter(env: &Env) -> OfferFilter {
+    OfferFilter {
+        categories: Vec::new(env),
+        payment_token: None,
+        min_price: None,
+        max_price: None,
+        min_reputation: None,
+        statuses: Vec::new(env),
+    }
+}
+
+#[test]
+fn test_list_offers_filters() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let vendor2 = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+    let (xlm, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+    client.register_token(&admin, &xlm.address);
+
+    usdc.mint(&vendor, &10000);
+    usdc.mint(&vendor2, &10000);
+    usdc.mint(&buyer, &1000);
+
+    let cheap = client.create_offer(
+        &vendor,
+        &Category::Airtime,
+        &String::from_str(&env, "MTN 500"),
+        &String::from_str(&env, "Airtime"),
+        &500,
+        &1,
+        &usdc.address,
+        &600,
+        &usdc.address,
+    );
+    let pricey = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &5000,
+        &1,
+        &xlm.address,
+        &6000,
+        &usdc.address,
+    );
+    let declined = client.create_offer(
+        &vendor2,
+        &Category::GiftCards,
+        &String::from_str(&env, "Steam $10"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &2,
+        &usdc.address,
+        &2400,
+        &usdc.address,
+    );
+    client.pause_offer(&vendor, &cheap);
+
+    // vendor2 turns down a trade and drops below a perfect score
+    let trade_id = client.accept_offer(&buyer, &declined, &1, &None, &None, &None, &None);
+    client.decline_trade(&vendor2, &trade_id);
+
+    let page = client.list_offers(&no_filter(&env), &0, &10);
+    assert_eq!(page.offers.len(), 3);
+    assert_eq!(page.next_cursor, 0);
+
+    let mut filter = no_filter(&env);
+    filter.statuses = vec![&env, OfferStatus::Active];
+    let page = client.list_offers(&filter, &0, &10);
+    assert_eq!(page.offers.len(), 2);
+
+    filter.categories = vec![&env, Category::GiftCards];
+    filter.payment_token = Some(usdc.address.clone());
+    let page = client.list_offers(&filter, &0, &10);
+    assert_eq!(page.offers.len(), 1);
+    assert_eq!(page.offers.get(0).unwrap().id, declined);
+
+    // A payment option in XLM counts as paying in XLM
+    client.set_payment_options(&vendor2, &declined, &vec![&env, PaymentOption { token: xlm.address.clone(), price: 10000 }]);
+    filter.payment_token = Some(xlm.address.clone());
+    let page = client.list_offers(&filter, &0, &10);
+    assert_eq!(page.offers.len(), 2);
+
+    let mut filter = no_filter(&env);
+    filter.min_price = Some(600);
+    filter.max_price = Some(5000);
+    let page = client.list_offers(&filter, &0, &10);
+    assert_eq!(page.offers.len(), 2);
+    assert_eq!(page.offers.get(0).unwrap().id, pricey);
+
+    filter.min_reputation = Some(50);
+    let page = client.list_offers(&filter, &0, &10);
+    assert_eq!(page.offers.len(), 1);
+    assert_eq!(page.offers.get(0).unwrap().id, pricey);
+}
+
+#[test]
+fn test_list_offers_pagination() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &6000);
+
+    for _ in 0..5 {
+        client.create_offer(
+            &vendor,
+            &Category::Airtime,
+            &String::from_str(&env, "MTN 1000"),
+            &String::from_str(&env, "Airtime"),
+            &1000,
+            &1,
+            &usdc.address,
+            &1200,
+            &usdc.address,
+        );
+    }
+
+    let first = client.list_offers(&no_filter(&env), &0, &2);
+    assert_eq!(first.offers.len(), 2);
+    assert_eq!(first.next_cursor, 2);
+
+    let second = client.list_offers(&no_filter(&env), &first.next_cursor, &2);
+    assert_eq!(second.offers.get(0).unwrap().id, 3);
+    assert_eq!(second.next_cursor, 4);
+
+    let last = client.list_offers(&no_filter(&env), &second.next_cursor, &2);
+    assert_eq!(last.offers.len(), 1);
+    assert_eq!(last.next_cursor, 0);
+
+    assert_eq!(
+        client.try_list_offers(&no_filter(&env), &0, &0).err(),
+        Some(Ok(P2PError::InvalidArgument))
+    );
+}
+
+#[test]
+fn test_buyer_stats_and_requirements() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let arbiter = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+    client.register_arbiter(&admin, &arbiter);
+
+    usdc.mint(&vendor, &3600);
+    usdc.mint(&buyer, &3000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &3,
+        &usdc.address,
+        &3600,
+        &usdc.address,
+    );
+
+    let trade1 = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+    client.mark_delivered(&vendor, &trade1, &String::from_str(&env, "CODE: 1"));
+    client.confirm_delivery(&buyer, &trade1);
+
+    // Buyer disputes a good delivery and loses
+    let trade2 = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+    client.mark_delivered(&vendor, &trade2, &String::from_str(&env, "CODE: 2"));
+    client.dispute_trade(&buyer, &trade2, &String::from_str(&env, "Never arrived"));
+    client.counter_dispute(&vendor, &trade2, &String::from_str(&env, "Redemption log"));
+    client.resolve_dispute(&arbiter, &trade2, &0, &0);
+
+    let stats = client.get_buyer_stats(&buyer);
+    assert_eq!(stats.total_trades, 2);
+    assert_eq!(stats.completed_trades, 1);
+    assert_eq!(stats.disputes_opened, 1);
+    assert_eq!(stats.disputes_lost, 1);
+    assert_eq!(stats.total_volume, 1000);
+    assert_eq!(stats.reputation_score, 50);
+
+    client.set_buyer_requirements(&vendor, &offer_id, &0, &true);
+    assert_eq!(
+        client.try_accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None),
+        Err(Ok(P2PError::BuyerNotEligible))
+    );
+
+    client.set_buyer_requirements(&vendor, &offer_id, &60, &false);
+    assert_eq!(
+        client.try_accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None),
+        Err(Ok(P2PError::BuyerNotEligible))
+    );
+
+    client.set_buyer_requirements(&vendor, &offer_id, &50, &false);
+    client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+
+    assert_eq!(
+        client.try_set_buyer_requirements(&vendor, &offer_id, &101, &false),
+        Err(Ok(P2PError::InvalidArgument))
+    );
+}
+
+#[test]
+fn test_reputation_weights_value_and_decays() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let arbiter = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+    client.register_arbiter(&admin, &arbiter);
+
+    let big = 1000_0000000i128;
+    let small = 10_0000000i128;
+    usdc.mint(&vendor, &((big + small) * 2));
+    usdc.mint(&buyer, &(big + small));
+
+    // New vendors start from the neutral prior
+    let rep = client.get_reputation(&vendor);
+    assert_eq!(rep.score, 50);
+    assert_eq!(rep.prior_volume, 500_0000000);
+
+    let big_offer = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $1000"),
+        &String::from_str(&env, "Digital code"),
+        &big,
+        &1,
+        &usdc.address,
+        &(big * 2),
+        &usdc.address,
+    );
+    let small_offer = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $10"),
+        &String::from_str(&env, "Digital code"),
+        &small,
+        &1,
+        &usdc.address,
+        &(small * 2),
+        &usdc.address,
+    );
+
+    let trade1 = client.accept_offer(&buyer, &big_offer, &1, &None, &None, &None, &None);
+    client.mark_delivered(&vendor, &trade1, &String::from_str(&env, "CODE: 1"));
+    client.confirm_delivery(&buyer, &trade1);
+    assert_eq!(client.get_reputation(&vendor).score, 83);
+
+    // A small fraud ruling counts triple its value, not as a whole trade
+    let trade2 = client.accept_offer(&buyer, &small_offer, &1, &None, &None, &None, &None);
+    client.mark_delivered(&vendor, &trade2, &String::from_str(&env, "CODE: 2"));
+    client.dispute_trade(&buyer, &trade2, &String::from_str(&env, "Code invalid"));
+    client.resolve_dispute(&arbiter, &trade2, &10000, &10000);
+
+    let rep = client.get_reputation(&vendor);
+    assert_eq!(rep.positive_volume, big);
+    assert_eq!(rep.negative_volume, small * 3);
+    assert_eq!(rep.score, 81);
+    assert_eq!(client.get_vendor_stats(&vendor).fraud_trades, 1);
+
+    // Outcomes halve after a half-life and the score drifts back toward the prior
+    env.ledger().with_mut(|li| li.timestamp += 180 * 86400);
+    let rep = client.get_reputation(&vendor);
+    assert_eq!(rep.positive_volume, big / 2);
+    assert_eq!(rep.negative_volume, small * 3 / 2);
+    assert_eq!(rep.score, 73);
+}
+
+#[test]
+fn test_reputation_normalises_token_decimals() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor_a = Address::generate(&env);
+    let vendor_b = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+    let (cents, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+    client.register_token(&admin, &cents.address);
+
+    // Registry reports a 2-decimal token
+    env.as_contract(&client.address, || {
+        let key = P2PDataKey::SupportedToken(cents.address.clone());
+        let mut info: TokenInfo = env.storage().instance().get(&key).unwrap();
+        info.decimals = 2;
+        env.storage().instance().set(&key, &info);
+    });
+
+    // The same 1000 whole units in each token
+    let usdc_price = 1000_0000000i128;
+    let cents_price = 100_000i128;
+    usdc.mint(&vendor_a, &(usdc_price * 2));
+    cents.mint(&vendor_b, &(cents_price * 2));
+    usdc.mint(&buyer, &usdc_price);
+    cents.mint(&buyer, &cents_price);
+
+    for (vendor, token, price) in [
+        (vendor_a.clone(), usdc.address.clone(), usdc_price),
+        (vendor_b.clone(), cents.address.clone(), cents_price),
+    ] {
+        let offer_id = client.create_offer(
+            &vendor,
+            &Category::GiftCards,
+            &String::from_str(&env, "Amazon $1000"),
+            &String::from_str(&env, "Digital code"),
+            &price,
+            &1,
+            &token,
+            &(price * 2),
+            &token,
+        );
+        let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+        client.mark_delivered(&vendor, &trade_id, &String::from_str(&env, "CODE"));
+        client.confirm_delivery(&buyer, &trade_id);
+    }
+
+    let rep_a = client.get_reputation(&vendor_a);
+    let rep_b = client.get_reputation(&vendor_b);
+    assert_eq!(rep_b.positive_volume, rep_a.positive_volume);
+    assert_eq!(rep_a.score, 83);
+    assert_eq!(rep_b.score, 83);
+}
+
+#[test]
+fn test_rate_trade_and_vendor_reviews() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    let price = 1000_0000000i128;
+    usdc.mint(&vendor, &(price * 36 / 10));
+    usdc.mint(&buyer, &(price * 3));
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $1000"),
+        &String::from_str(&env, "Digital code"),
+        &price,
+        &3,
+        &usdc.address,
+        &(price * 36 / 10),
+        &usdc.address,
+    );
+
+    let mut trades = Vec::new(&env);
+    for _ in 0..2 {
+        let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+        client.mark_delivered(&vendor, &trade_id, &String::from_str(&env, "CODE"));
+        client.confirm_delivery(&buyer, &trade_id);
+        trades.push_back(trade_id);
+    }
+    let open_trade = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+    assert_eq!(client.get_reputation(&vendor).score, 90);
+
+    client.rate_trade(&buyer, &trades.get(0).unwrap(), &5, &String::from_str(&env, "Instant"));
+    client.rate_trade(&buyer, &trades.get(1).unwrap(), &4, &String::from_str(&env, "Good"));
+    client.rate_trade(&vendor, &trades.get(0).unwrap(), &3, &String::from_str(&env, "Slow to confirm"));
+
+    let stats = client.get_vendor_stats(&vendor);
+    assert_eq!(stats.rating_count, 2);
+    assert_eq!(stats.rating_average, 450);
+    // 5 stars earn the full trade value, 4 stars three quarters of it
+    let rep = client.get_reputation(&vendor);
+    assert_eq!(rep.rated_volume, price * 2);
+    assert_eq!(rep.rating_credit, price * 7 / 4);
+    assert_eq!(stats.reputation_score, 88);
+    assert_eq!(client.get_buyer_stats(&buyer).rating_average, 300);
+
+    assert_eq!(
+        client.try_rate_trade(&buyer, &trades.get(0).unwrap(), &1, &String::from_str(&env, "Changed my mind")),
+        Err(Ok(P2PError::AlreadyRated))
+    );
+    assert_eq!(
+        client.try_rate_trade(&buyer, &open_trade, &5, &String::from_str(&env, "Early")),
+        Err(Ok(P2PError::InvalidTradeState))
+    );
+    assert_eq!(
+        client.try_rate_trade(&vendor, &trades.get(1).unwrap(), &6, &String::from_str(&env, "")),
+        Err(Ok(P2PError::InvalidArgument))
+    );
+
+    // Only buyer reviews are listed for the vendor
+    let page = client.get_vendor_reviews(&vendor, &0, &1);
+    assert_eq!(page.reviews.len(), 1);
+    assert_eq!(page.reviews.get(0).unwrap().stars, 5);
+    assert_eq!(page.next_cursor, 1);
+
+    let page = client.get_vendor_reviews(&vendor, &page.next_cursor, &10);
+    assert_eq!(page.reviews.len(), 1);
+    assert_eq!(page.reviews.get(0).unwrap().comment, String::from_str(&env, "Good"));
+    assert_eq!(page.next_cursor, 0);
+}
+
+#[test]
+fn test_sealed_delivery_proof() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &2400);
+    usdc.mint(&buyer, &2000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &2,
+        &usdc.address,
+        &2400,
+        &usdc.address,
+    );
+
+    // Fixed key and seed keep the snapshot stable across runs
+    let buyer_secret = [9u8; 32];
+    let buyer_public = p2p_proof::public_key(&buyer_secret);
+    let buyer_key = Some(BytesN::from_array(&env, &buyer_public));
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &buyer_key, &None, &None, &None);
+
+    // A buyer key rules out plaintext delivery
+    assert_eq!(
+        client.try_mark_delivered(&vendor, &trade_id, &String::from_str(&env, "AMZN-4821")),
+        Err(Ok(P2PError::ProofMustBeSealed))
+    );
+
+    let sealed = p2p_proof::seal_with_rng(&buyer_public, b"AMZN-4821", &mut ChaCha20Rng::from_seed([1u8; 32]));
+    client.mark_delivered_sealed(
+        &vendor,
+        &trade_id,
+        &Bytes::from_slice(&env, &sealed.envelope),
+        &BytesN::from_array(&env, &sealed.commitment),
+    );
+
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.status, TradeStatus::Delivered);
+    assert_eq!(trade.delivery_proof, String::from_str(&env, ""));
+
+    // Only the buyer can read the proof, and it matches the on-chain commitment
+    let envelope: std::vec::Vec<u8> = trade.sealed_proof.iter().collect();
+    let opened = p2p_proof::open(&buyer_secret, &envelope).unwrap();
+    assert_eq!(opened.proof, b"AMZN-4821");
+    let commitment = trade.proof_commitment.unwrap().to_array();
+    assert!(p2p_proof::verify(&opened.proof, &opened.salt, &commitment));
+
+    // Trades without a buyer key can't take sealed proofs
+    let plain_trade = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+    assert_eq!(
+        client.try_mark_delivered_sealed(
+            &vendor,
+            &plain_trade,
+            &Bytes::from_slice(&env, &sealed.envelope),
+            &BytesN::from_array(&env, &sealed.commitment),
+        ),
+        Err(Ok(P2PError::NoBuyerKey))
+    );
+}
+
+#[test]
+fn test_committed_proof_reveal_on_dispute() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &3600);
+    usdc.mint(&buyer, &3000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::Airtime,
+        &String::from_str(&env, "MTN 1000"),
+        &String::from_str(&env, "Recharge PIN"),
+        &1000,
+        &3,
+        &usdc.address,
+        &3600,
+        &usdc.address,
+    );
+
+    let salt = [7u8; 32];
+    let commitment = BytesN::from_array(&env, &p2p_proof::commitment(b"PIN-5531", &salt));
+    let salt = BytesN::from_array(&env, &salt);
+    let proof = Bytes::from_slice(&env, b"PIN-5531");
+
+    // The honest vendor reveals without countering first
+    let mut trades = Vec::new(&env);
+    for i in 0..3 {
+        let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+        client.mark_delivered_committed(&vendor, &trade_id, &commitment);
+        client.dispute_trade(&buyer, &trade_id, &String::from_str(&env, "PIN rejected"));
+        if i > 0 {
+            client.counter_dispute(&vendor, &trade_id, &String::from_str(&env, "PIN was valid"));
+        }
+        trades.push_back(trade_id);
+    }
+    let (honest, silent, wrong) = (trades.get(0).unwrap(), trades.get(1).unwrap(), trades.get(2).unwrap());
+
+    // Only the hash is on-chain until the vendor reveals
+    assert_eq!(client.get_trade(&honest).proof_commitment, Some(commitment.clone()));
+    assert_eq!(client.get_trade(&honest).revealed_proof.len(), 0);
+
+    assert!(client.reveal_proof(&vendor, &honest, &proof, &salt));
+    assert!(!client.reveal_proof(&vendor, &wrong, &Bytes::from_slice(&env, b"PIN-0000"), &salt));
+
+    let trade = client.get_trade(&honest);
+    assert_eq!(trade.proof_matches, Some(true));
+    assert_eq!(trade.revealed_proof, proof);
+    assert_eq!(trade.status, TradeStatus::DisputedVendor);
+    assert_eq!(client.get_trade(&wrong).proof_matches, Some(false));
+    assert_eq!(
+        client.try_reveal_proof(&vendor, &honest, &proof, &salt),
+        Err(Ok(P2PError::ProofAlreadyRevealed))
+    );
+
+    env.ledger().with_mut(|li| li.timestamp += 604801);
+
+    // A matching reveal goes to the arbiter; a missing or wrong one loses by default
+    assert_eq!(
+        client.try_claim_default_ruling(&honest),
+        Err(Ok(P2PError::InvalidTradeState))
+    );
+    assert_eq!(
+        client.try_reveal_proof(&vendor, &silent, &proof, &salt),
+        Err(Ok(P2PError::DisputeWindowClosed))
+    );
+    client.claim_default_ruling(&silent);
+    client.claim_default_ruling(&wrong);
+
+    assert_eq!(client.get_trade(&silent).status, TradeStatus::Resolved);
+    assert_eq!(client.get_trade(&wrong).status, TradeStatus::Resolved);
+    // Two refunds plus the slashed reservations
+    assert_eq!(usdc_token.balance(&buyer), 2 * (1000 + 1200));
+}
+
+#[test]
+fn test_milestone_release_and_dispute_remainder() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let arbiter = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+    client.register_arbiter(&admin, &arbiter);
+
+    usdc.mint(&vendor, &2400);
+    usdc.mint(&buyer, &1000);
+
+    let gift_card = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &1,
+        &usdc.address,
+        &1200,
+        &usdc.address,
+    );
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::Services,
+        &String::from_str(&env, "Logo design"),
+        &String::from_str(&env, "Sketches, then final files"),
+        &1000,
+        &1,
+        &usdc.address,
+        &1200,
+        &usdc.address,
+    );
+
+    // Only Services offers, and shares must cover the whole price
+    assert_eq!(
+        client.try_set_milestones(&vendor, &gift_card, &vec![&env, 3000, 7000]),
+        Err(Ok(P2PError::InvalidArgument))
+    );
+    assert_eq!(
+        client.try_set_milestones(&vendor, &offer_id, &vec![&env, 3000, 6000]),
+        Err(Ok(P2PError::InvalidArgument))
+    );
+    client.set_milestones(&vendor, &offer_id, &vec![&env, 3000, 7000]);
+
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+
+    // First milestone releases 30% of the payment and of the reservation
+    client.mark_delivered(&vendor, &trade_id, &String::from_str(&env, "Sketches"));
+    client.confirm_delivery(&buyer, &trade_id);
+
+    assert_eq!(usdc_token.balance(&vendor), 300);
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.status, TradeStatus::Funded);
+    assert_eq!(trade.milestones_released, 1);
+    assert_eq!(trade.released_amount, 300);
+    assert_eq!(client.get_offer(&offer_id).collateral.reserved, 840);
+
+    // Dispute only covers the unreleased 70%
+    client.mark_delivered(&vendor, &trade_id, &String::from_str(&env, "Final files"));
+    client.dispute_trade(&buyer, &trade_id, &String::from_str(&env, "Files never arrived"));
+    client.resolve_dispute(&arbiter, &trade_id, &10000, &10000);
+
+    assert_eq!(usdc_token.balance(&buyer), 700 + 840);
+    assert_eq!(usdc_token.balance(&vendor), 300);
+
+    let offer = client.get_offer(&offer_id);
+    assert_eq!(offer.collateral.amount, 360);
+    assert_eq!(offer.collateral.reserved, 0);
+    assert_eq!(client.get_trade(&trade_id).status, TradeStatus::Resolved);
+}
+
+#[test]
+fn test_cross_chain_htlc_claim_and_refund() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let arbiter = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+    client.register_arbiter(&admin, &arbiter);
+
+    usdc.mint(&vendor, &3600);
+    usdc.mint(&buyer, &3000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::CrossChain,
+        &String::from_str(&env, "USDC to ETH"),
+        &String::from_str(&env, "Swap via HTLC"),
+        &1000,
+        &3,
+        &usdc.address,
+        &3600,
+        &usdc.address,
+    );
+
+    let preimage = BytesN::from_array(&env, &[42u8; 32]);
+    let hashlock: BytesN<32> = env.crypto().sha256(&Bytes::from(preimage.clone())).to_bytes();
+
+    assert_eq!(
+        client.try_accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None),
+        Err(Ok(P2PError::InvalidArgument))
+    );
+
+    // Claimed trade: payment goes out with the preimage, collateral stays reserved
+    let claimed = client.accept_offer(&buyer, &offer_id, &1, &None, &Some(hashlock.clone()), &None, &None);
+    assert_eq!(
+        client.try_mark_delivered(&vendor, &claimed, &String::from_str(&env, "sent")),
+        Err(Ok(P2PError::InvalidTradeState))
+    );
+    assert_eq!(
+        client.try_claim_htlc(&vendor, &claimed, &BytesN::from_array(&env, &[0u8; 32])),
+        Err(Ok(P2PError::InvalidArgument))
+    );
+    client.claim_htlc(&vendor, &claimed, &preimage);
+
+    let trade = client.get_trade(&claimed);
+    assert_eq!(trade.status, TradeStatus::Delivered);
+    assert_eq!(trade.preimage, Some(preimage.clone()));
+    assert_eq!(usdc_token.balance(&vendor), 1000);
+    assert_eq!(client.get_offer(&offer_id).collateral.reserved, 1200);
+
+    client.confirm_delivery(&buyer, &claimed);
+    assert_eq!(usdc_token.balance(&vendor), 1000);
+    assert_eq!(client.get_offer(&offer_id).collateral.reserved, 0);
+
+    // Claimed but never honoured on the other chain: collateral covers the buyer
+    let failed = client.accept_offer(&buyer, &offer_id, &1, &None, &Some(hashlock.clone()), &None, &None);
+    client.claim_htlc(&vendor, &failed, &preimage);
+    client.dispute_trade(&buyer, &failed, &String::from_str(&env, "ETH never arrived"));
+    client.resolve_dispute(&arbiter, &failed, &10000, &10000);
+    assert_eq!(usdc_token.balance(&buyer), 1000 + 1200);
+    assert_eq!(usdc_token.balance(&vendor), 2000);
+    assert_eq!(client.get_buyer_stats(&buyer).disputes_lost, 0);
+
+    // Unclaimed trade refunds after the timelock
+    let unclaimed = client.accept_offer(&buyer, &offer_id, &1, &None, &Some(hashlock), &None, &None);
+    assert_eq!(
+        client.try_refund_htlc(&buyer, &unclaimed),
+        Err(Ok(P2PError::AutoCompleteWindowOpen))
+    );
+
+    env.ledger().with_mut(|li| li.timestamp += 86401);
+    assert_eq!(
+        client.try_claim_htlc(&vendor, &unclaimed, &preimage),
+        Err(Ok(P2PError::InvalidTradeState))
+    );
+    client.refund_htlc(&buyer, &unclaimed);
+
+    assert_eq!(client.get_trade(&unclaimed).status, TradeStatus::Cancelled);
+    assert_eq!(usdc_token.balance(&buyer), 1200 + 1000);
+    assert_eq!(client.get_offer(&offer_id).quantity_remaining, 1);
+}
+
+#[test]
+fn test_buyer_request_quotes_become_trade() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let vendor1 = Address::generate(&env);
+    let vendor2 = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&buyer, &1500);
+    usdc.mint(&vendor1, &1440);
+    usdc.mint(&vendor2, &1200);
+
+    let request_id = client.create_request(
+        &buyer,
+        &Category::Airtime,
+        &String::from_str(&env, "5000 NGN MTN airtime"),
+        &String::from_str(&env, "Top up 0803..."),
+        &usdc.address,
+        &1500,
+    );
+    assert_eq!(usdc_token.balance(&buyer), 0);
+    assert_eq!(client.get_category_requests(&Category::Airtime), vec![&env, request_id]);
+
+    assert_eq!(
+        client.try_submit_quote(&vendor1, &request_id, &1600, &1920, &String::from_str(&env, "")),
+        Err(Ok(P2PError::InvalidAmount))
+    );
+    assert_eq!(
+        client.try_submit_quote(&vendor1, &request_id, &1200, &1000, &String::from_str(&env, "")),
+        Err(Ok(P2PError::InsufficientCollateral))
+    );
+
+    let losing = client.submit_quote(&vendor1, &request_id, &1200, &1440, &String::from_str(&env, "Within 1h"));
+    let winning = client.submit_quote(&vendor2, &request_id, &1000, &1200, &String::from_str(&env, "Instant"));
+    assert_eq!(client.get_request_quotes(&request_id), vec![&env, losing, winning]);
+
+    assert_eq!(
+        client.try_accept_quote(&vendor1, &winning, &None, &None),
+        Err(Ok(P2PError::NotRequestOwner))
+    );
+
+    // A floor raised after quoting leaves the locked collateral short
+    client.set_token_limits(&admin, &usdc.address, &150, &0, &0);
+    assert_eq!(
+        client.try_accept_quote(&buyer, &winning, &None, &None),
+        Err(Ok(P2PError::InsufficientFreeCollateral))
+    );
+    client.set_token_limits(&admin, &usdc.address, &0, &0, &0);
+
+    let trade_id = client.accept_quote(&buyer, &winning, &None, &None);
+
+    // Unused budget and the losing collateral go back
+    assert_eq!(usdc_token.balance(&buyer), 500);
+    assert_eq!(usdc_token.balance(&vendor1), 1440);
+    assert_eq!(client.get_quote(&losing).status, QuoteStatus::Rejected);
+    assert_eq!(client.get_category_requests(&Category::Airtime).len(), 0);
+
+    let request = client.get_request(&request_id);
+    assert_eq!(request.status, RequestStatus::Filled);
+    assert_eq!(request.trade_id, trade_id);
+    assert_eq!(
+        client.try_submit_quote(&vendor1, &request_id, &900, &1080, &String::from_str(&env, "")),
+        Err(Ok(P2PError::InvalidOfferState))
+    );
+
+    // The winning quote settles as a normal trade on an unlisted offer
+    let offer_id = client.get_quote(&winning).offer_id;
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.offer_id, offer_id);
+    assert_eq!(trade.amount, 1000);
+    assert_eq!(client.get_category_offers(&Category::Airtime).len(), 0);
+
+    client.mark_delivered(&vendor2, &trade_id, &String::from_str(&env, "Recharged"));
+    client.confirm_delivery(&buyer, &trade_id);
+    client.cancel_offer(&vendor2, &offer_id);
+
+    assert_eq!(usdc_token.balance(&vendor2), 1000 + 1200);
+    assert_eq!(usdc_token.balance(&client.address), 0);
+}
+
+#[test]
+fn test_cancel_request_releases_quotes() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&buyer, &1000);
+    usdc.mint(&vendor, &2400);
+
+    let request_id = client.create_request(
+        &buyer,
+        &Category::Services,
+        &String::from_str(&env, "Landing page"),
+        &String::from_str(&env, "One page, responsive"),
+        &usdc.address,
+        &1000,
+    );
+    let withdrawn = client.submit_quote(&vendor, &request_id, &1000, &1200, &String::from_str(&env, "2 days"));
+    let pending = client.submit_quote(&vendor, &request_id, &900, &1080, &String::from_str(&env, "3 days"));
+
+    client.withdraw_quote(&vendor, &withdrawn);
+    assert_eq!(client.get_quote(&withdrawn).status, QuoteStatus::Withdrawn);
+    assert_eq!(
+        client.try_accept_quote(&buyer, &withdrawn, &None, &None),
+        Err(Ok(P2PError::InvalidOfferState))
+    );
+
+    client.cancel_request(&buyer, &request_id);
+
+    assert_eq!(client.get_request(&request_id).status, RequestStatus::Cancelled);
+    assert_eq!(client.get_quote(&pending).status, QuoteStatus::Rejected);
+    assert_eq!(usdc_token.balance(&buyer), 1000);
+    assert_eq!(usdc_token.balance(&vendor), 2400);
+    assert_eq!(client.get_category_requests(&Category::Services).len(), 0);
+}
+
+#[test]
+fn test_expire_offers_returns_collateral() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+
+    usdc.mint(&vendor, &4800);
+    usdc.mint(&buyer, &2000);
+
+    let idle = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &1,
+        &usdc.address,
+        &1200,
+        &usdc.address,
+    );
+    let busy = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Steam $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &2,
+        &usdc.address,
+        &2400,
+        &usdc.address,
+    );
+
+    let paused = client.create_offer(
+        &vendor,
+        &Category::Gaming,
+        &String::from_str(&env, "Xbox $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &1,
+        &usdc.address,
+        &1200,
+        &usdc.address,
+    );
+    client.pause_offer(&vendor, &paused);
+
+    env.ledger().with_mut(|li| li.timestamp = 1_000);
+    let now = env.ledger().timestamp();
+    assert_eq!(
+        client.try_set_offer_expiry(&vendor, &idle, &now),
+        Err(Ok(P2PError::InvalidArgument))
+    );
+    client.set_offer_expiry(&vendor, &idle, &(now + 3600));
+    client.set_offer_expiry(&vendor, &busy, &(now + 3600));
+    client.set_offer_expiry(&vendor, &paused, &(now + 3600));
+
+    let trade_id = client.accept_offer(&buyer, &busy, &1, &None, &None, &None, &None);
+    client.mark_delivered(&vendor, &trade_id, &String::from_str(&env, "CODE: XXXX"));
+
+    env.ledger().with_mut(|li| li.timestamp += 3600);
+
+    // Expired offers drop out of queries and can't be bought before the sweep
+    assert_eq!(client.get_category_offers(&Category::GiftCards).len(), 0);
+    assert_eq!(client.get_active_offers().len(), 0);
+    assert_eq!(
+        client.try_accept_offer(&buyer, &busy, &1, &None, &None, &None, &None),
+        Err(Ok(P2PError::OfferNotActive))
+    );
+
+    // Each call inspects `limit` offers; only those without an active trade are
+    // retired, paused ones included
+    assert_eq!(client.expire_offers(&0, &2), (1, 2));
+    assert_eq!(client.get_offer(&idle).status, OfferStatus::Expired);
+    assert_eq!(client.get_offer(&busy).status, OfferStatus::Active);
+    assert_eq!(client.expire_offers(&2, &2), (1, 0));
+    assert_eq!(client.get_offer(&paused).status, OfferStatus::Expired);
+    assert_eq!(usdc_token.balance(&vendor), 1200 + 1200);
+    assert_eq!(
+        client.try_cancel_offer(&vendor, &idle),
+        Err(Ok(P2PError::InvalidOfferState))
+    );
+
+    client.confirm_delivery(&buyer, &trade_id);
+    assert_eq!(client.expire_offers(&0, &10), (1, 0));
+    assert_eq!(client.get_offer(&busy).status, OfferStatus::Expired);
+    assert_eq!(usdc_token.balance(&vendor), 1200 + 1200 + 1000 + 2400);
+    assert_eq!(usdc_token.balance(&client.address), 0);
+}
+
+#[test]
+fn test_reference_priced_offer_uses_oracle() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|li| li.timestamp = 10_000);
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+    let (xlm, xlm_token) = create_token_contract(&env, &token_admin);
+    let (eurc, _) = create_token_contract(&env, &token_admin);
+    let oracle = MockOracleClient::new(&env, &env.register(MockOracle, ()));
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+    client.register_token(&admin, &xlm.address);
+    client.register_token(&admin, &eurc.address);
+
+    usdc.mint(&vendor, &120_0000000);
+    xlm.mint(&buyer, &1000_0000000);
+
+    // $50 gift cards, two in stock, collateral in USDC
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &50_0000000,
+        &2,
+        &usdc.address,
+        &120_0000000,
+        &usdc.address,
+    );
+
+    assert_eq!(
+        client.try_set_reference_price(&vendor, &offer_id, &5000, &vec![&env, xlm.address.clone()]),
+        Err(Ok(P2PError::PriceUnavailable))
+    );
+
+    client.set_oracle(&admin, &oracle.address, &300);
+    oracle.set_price(&usdc.address, &100_000_000_000_000, &10_000);     // $1.00
+    oracle.set_price(&xlm.address, &10_000_000_000_000, &10_000);       // $0.10
+    client.set_reference_price(&vendor, &offer_id, &5000, &vec![&env, xlm.address.clone()]);
+
+    let mut filter = no_filter(&env);
+    filter.payment_token = Some(xlm.address.clone());
+    assert_eq!(client.list_offers(&filter, &0, &10).offers.len(), 1);
+    filter.payment_token = Some(eurc.address.clone());
+    assert_eq!(client.list_offers(&filter, &0, &10).offers.len(), 0);
+
+    assert_eq!(
+        client.try_accept_offer(&buyer, &offer_id, &1, &None, &None, &Some(eurc.address.clone()), &None),
+        Err(Ok(P2PError::TokenNotSupported))
+    );
+
+    // $50 at $0.10 is 500 XLM; the reservation is 120% of $50 in USDC
+    let trade_id = client.accept_offer(
+        &buyer, &offer_id, &1, &None, &None, &Some(xlm.address.clone()), &Some(500_0000000),
+    );
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.payment_token, xlm.address);
+    assert_eq!(trade.amount, 500_0000000);
+    assert_eq!(trade.collateral_amount, 60_0000000);
+    assert_eq!(xlm_token.balance(&buyer), 500_0000000);
+
+    // USDC slips to $0.90: the last card needs 66.67 USDC but only 60 is free,
+    // however low the fixed fallback price is set
+    oracle.set_price(&usdc.address, &90_000_000_000_000, &10_000);
+    assert_eq!(
+        client.try_update_offer(
+            &vendor,
+            &offer_id,
+            &String::from_str(&env, "Amazon $50"),
+            &String::from_str(&env, "Digital code"),
+            &1,
+        ),
+        Err(Ok(P2PError::InsufficientCollateral))
+    );
+    oracle.set_price(&usdc.address, &100_000_000_000_000, &10_000);
+
+    // XLM drops to $0.08: the buyer's cap now protects them
+    oracle.set_price(&xlm.address, &8_000_000_000_000, &10_000);
+    assert_eq!(
+        client.try_accept_offer(&buyer, &offer_id, &1, &None, &None, &Some(xlm.address.clone()), &Some(500_0000000)),
+        Err(Ok(P2PError::SlippageExceeded))
+    );
+
+    env.ledger().with_mut(|li| li.timestamp += 301);
+    assert_eq!(
+        client.try_accept_offer(&buyer, &offer_id, &1, &None, &None, &Some(xlm.address.clone()), &None),
+        Err(Ok(P2PError::StalePrice))
+    );
+
+    client.mark_delivered(&vendor, &trade_id, &String::from_str(&env, "CODE: XXXX"));
+    client.confirm_delivery(&buyer, &trade_id);
+    assert_eq!(xlm_token.balance(&vendor), 500_0000000);
+}
+
+#[test]
+fn test_reference_price_in_high_decimal_token() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|li| li.timestamp = 10_000);
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (weth, _) = create_token_contract(&env, &token_admin);
+    let oracle = MockOracleClient::new(&env, &env.register(MockOracle, ()));
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &weth.address);
+    client.set_oracle(&admin, &oracle.address, &300);
+    oracle.set_price(&weth.address, &100_000_000_000_000, &10_000);     // $1.00
+
+    // Treat the token as 18 decimals
+    env.as_contract(&client.address, || {
+        let key = P2PDataKey::SupportedToken(weth.address.clone());
+        let mut info: TokenInfo = env.storage().instance().get(&key).unwrap();
+        info.decimals = 18;
+        env.storage().instance().set(&key, &info);
+    });
+
+    let unit = 10i128.pow(18);
+    weth.mint(&vendor, &(24_000 * unit));
+    weth.mint(&buyer, &(20_000 * unit));
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::Services,
+        &String::from_str(&env, "Annual retainer"),
+        &String::from_str(&env, "Priced in USD"),
+        &1,
+        &1,
+        &weth.address,
+        &(24_000 * unit),
+        &weth.address,
+    );
+
+    // Amounts too large for i128 are refused rather than panicking
+    assert_eq!(
+        client.try_set_reference_price(&vendor, &offer_id, &10i128.pow(33), &vec![&env]),
+        Err(Ok(P2PError::InvalidAmount))
+    );
+
+    // $20,000 is 2 * 10^22 base units
+    client.set_reference_price(&vendor, &offer_id, &2_000_000, &vec![&env]);
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+    assert_eq!(client.get_trade(&trade_id).amount, 20_000 * unit);
+}
+
+#[test]
+fn test_payment_options_and_deregister_token() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, _) = create_token_contract(&env, &token_admin);
+    let (xlm, xlm_token) = create_token_contract(&env, &token_admin);
+    let (unlisted, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+    client.register_token(&admin, &xlm.address);
+
+    usdc.mint(&vendor, &4800);
+    xlm.mint(&buyer, &20000);
+
+    // Payment tokens must be registered too, not just collateral
+    assert_eq!(
+        client.try_create_offer(
+            &vendor,
+            &Category::GiftCards,
+            &String::from_str(&env, "Amazon $50"),
+            &String::from_str(&env, "Digital code"),
+            &1000,
+            &2,
+            &unlisted.address,
+            &2400,
+            &usdc.address,
+        ),
+        Err(Ok(P2PError::TokenNotSupported))
+    );
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &2,
+        &usdc.address,
+        &2400,
+        &usdc.address,
+    );
+
+    let xlm_option = PaymentOption { token: xlm.address.clone(), price: 9000 };
+    assert_eq!(
+        client.try_set_payment_options(&vendor, &offer_id, &vec![&env, xlm_option.clone(), xlm_option.clone()]),
+        Err(Ok(P2PError::InvalidArgument))
+    );
+    assert_eq!(
+        client.try_set_payment_options(
+            &vendor,
+            &offer_id,
+            &vec![&env, PaymentOption { token: unlisted.address.clone(), price: 9000 }],
+        ),
+        Err(Ok(P2PError::TokenNotSupported))
+    );
+    client.set_payment_options(&vendor, &offer_id, &vec![&env, xlm_option]);
+
+    // Buyer pays the XLM price; collateral is reserved as for a USDC sale
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &Some(xlm.address.clone()), &None);
+    let trade = client.get_trade(&trade_id);
+    assert_eq!(trade.payment_token, xlm.address);
+    assert_eq!(trade.amount, 9000);
+    assert_eq!(trade.collateral_amount, 1200);
+
+    assert_eq!(
+        client.try_accept_offer(&buyer, &offer_id, &1, &None, &None, &Some(unlisted.address.clone()), &None),
+        Err(Ok(P2PError::TokenNotSupported))
+    );
+
+    // Deregistering blocks new trades in XLM but the funded one still settles
+    client.deregister_token(&admin, &xlm.address);
+    assert_eq!(
+        client.try_accept_offer(&buyer, &offer_id, &1, &None, &None, &Some(xlm.address.clone()), &None),
+        Err(Ok(P2PError::TokenNotSupported))
+    );
+    assert_eq!(
+        client.try_deregister_token(&admin, &xlm.address),
+        Err(Ok(P2PError::TokenNotSupported))
+    );
+
+    client.mark_delivered(&vendor, &trade_id, &String::from_str(&env, "CODE: XXXX"));
+    client.confirm_delivery(&buyer, &trade_id);
+    assert_eq!(xlm_token.balance(&vendor), 9000);
+    assert_eq!(xlm_token.balance(&buyer), 11000);
+}
+
+#[test]
+fn test_token_registry_and_limits() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+    let (xlm, _) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+    client.register_token(&admin, &xlm.address);
+
+    let tokens = client.get_supported_tokens();
+    assert_eq!(tokens.len(), 2);
+    let info = tokens.get(0).unwrap();
+    assert_eq!(info.token, usdc.address);
+    assert_eq!(info.symbol, usdc_token.symbol());
+    assert_eq!(info.decimals, 7);
+
+    // USDC collateral must cover 150% and trades must stay within 100..=6000
+    assert_eq!(
+        client.try_set_token_limits(&admin, &usdc.address, &150, &100, &50),
+        Err(Ok(P2PError::InvalidArgument))
+    );
+    client.set_token_limits(&admin, &usdc.address, &150, &100, &6000);
+    assert_eq!(client.get_token_info(&usdc.address).min_collateral_percent, 150);
+
+    usdc.mint(&vendor, &15000);
+    usdc.mint(&buyer, &10000);
+
+    assert_eq!(
+        client.try_create_offer(
+            &vendor,
+            &Category::GiftCards,
+            &String::from_str(&env, "Amazon $50"),
+            &String::from_str(&env, "Digital code"),
+            &5000,
+            &2,
+            &usdc.address,
+            &12000,
+            &usdc.address,
+        ),
+        Err(Ok(P2PError::InsufficientCollateral))
+    );
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &5000,
+        &2,
+        &usdc.address,
+        &15000,
+        &usdc.address,
+    );
+    assert_eq!(client.get_offer(&offer_id).collateral.token_symbol, usdc_token.symbol());
+
+    assert_eq!(
+        client.try_accept_offer(&buyer, &offer_id, &2, &None, &None, &None, &None),
+        Err(Ok(P2PError::InvalidAmount))
+    );
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+    assert_eq!(client.get_trade(&trade_id).collateral_amount, 7500);
+
+    client.deregister_token(&admin, &xlm.address);
+    let tokens = client.get_supported_tokens();
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(tokens.get(0).unwrap().token, usdc.address);
+}
+
+#[test]
+fn test_collateral_yield_and_illiquid_pool() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let arbiter = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+    let pool = MockPoolClient::new(&env, &env.register(MockPool, ()));
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+    client.register_arbiter(&admin, &arbiter);
+
+    usdc.mint(&vendor, &2400);
+    usdc.mint(&buyer, &1000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::GiftCards,
+        &String::from_str(&env, "Amazon $50"),
+        &String::from_str(&env, "Digital code"),
+        &1000,
+        &2,
+        &usdc.address,
+        &2400,
+        &usdc.address,
+    );
+
+    assert_eq!(
+        client.try_supply_collateral(&vendor, &offer_id, &2000),
+        Err(Ok(P2PError::PoolNotSet))
+    );
+    client.set_yield_pool(&admin, &pool.address);
+
+    client.supply_collateral(&vendor, &offer_id, &2000);
+    assert_eq!(
+        client.try_supply_collateral(&vendor, &offer_id, &500),
+        Err(Ok(P2PError::InsufficientFreeCollateral))
+    );
+    assert_eq!(usdc_token.balance(&pool.address), 2000);
+
+    // Pool pays 100 in interest
+    usdc.mint(&pool.address, &100);
+    pool.accrue(&client.address, &usdc.address, &100);
+    assert_eq!(client.get_supplied_collateral(&offer_id), 2100);
+
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+    client.mark_delivered(&vendor, &trade_id, &String::from_str(&env, "CODE: INVALID"));
+    client.dispute_trade(&buyer, &trade_id, &String::from_str(&env, "Code already used"));
+
+    // The slash needs pooled collateral the pool can't return yet
+    pool.set_liquidity(&Some(500));
+    assert_eq!(
+        client.try_resolve_dispute(&arbiter, &trade_id, &10000, &10000),
+        Err(Ok(P2PError::PoolIlliquid))
+    );
+    assert_eq!(client.get_trade(&trade_id).status, TradeStatus::DisputedBuyer);
+    assert_eq!(client.get_supplied_collateral(&offer_id), 2100);
+
+    pool.set_liquidity(&None);
+    client.resolve_dispute(&arbiter, &trade_id, &10000, &10000);
+    assert_eq!(usdc_token.balance(&buyer), 1000 + 1200);
+
+    // Interest stays with the vendor's collateral
+    let offer = client.get_offer(&offer_id);
+    assert_eq!(offer.collateral.yield_accrued, 100);
+    assert_eq!(offer.collateral.supplied, 0);
+    assert_eq!(offer.collateral.amount, 2400 + 100 - 1200);
+
+    client.cancel_offer(&vendor, &offer_id);
+    assert_eq!(usdc_token.balance(&vendor), 1300);
+    assert_eq!(usdc_token.balance(&client.address), 0);
+    assert_eq!(usdc_token.balance(&pool.address), 0);
+}
+
+#[test]
+fn test_insurance_fund_covers_ruling_shortfall() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let arbiter = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+
+    // 2.5% fee
+    client.initialize(&admin, &120, &86400, &604800, &250, &admin);
+    client.register_token(&admin, &usdc.address);
+    client.register_arbiter(&admin, &arbiter);
+
+    assert_eq!(
+        client.try_set_insurance_config(&admin, &10001, &0),
+        Err(Ok(P2PError::InvalidArgument))
+    );
+    // Half of every slash and a fifth of fees go to insurance
+    client.set_insurance_config(&admin, &5000, &2000);
+    assert_eq!(client.get_insurance_config(), (5000, 2000));
+
+    usdc.mint(&vendor, &2400);
+    usdc.mint(&buyer, &2000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::CrossChain,
+        &String::from_str(&env, "USDC to ETH"),
+        &String::from_str(&env, "Swap via HTLC"),
+        &1000,
+        &2,
+        &usdc.address,
+        &2400,
+        &usdc.address,
+    );
+
+    let preimage = BytesN::from_array(&env, &[7u8; 32]);
+    let hashlock: BytesN<32> = env.crypto().sha256(&Bytes::from(preimage.clone())).to_bytes();
+
+    // Completed trade: 25 fee, 5 of it insured
+    let completed = client.accept_offer(&buyer, &offer_id, &1, &None, &Some(hashlock.clone()), &None, &None);
+    client.claim_htlc(&vendor, &completed, &preimage);
+    client.confirm_delivery(&buyer, &completed);
+    assert_eq!(client.get_fee_balance(&usdc.address), 20);
+    assert_eq!(client.get_insurance_balance(&usdc.address), 5);
+
+    // Payment already claimed, so the ruling can only draw on the slash
+    let failed = client.accept_offer(&buyer, &offer_id, &1, &None, &Some(hashlock), &None, &None);
+    client.claim_htlc(&vendor, &failed, &preimage);
+    client.dispute_trade(&buyer, &failed, &String::from_str(&env, "ETH never arrived"));
+
+    assert_eq!(
+        client.try_claim_insurance(&buyer, &failed),
+        Err(Ok(P2PError::InvalidTradeState))
+    );
+
+    client.resolve_dispute(&arbiter, &failed, &10000, &10000);
+
+    // 1200 slashed: 600 to the buyer, 600 to insurance
+    assert_eq!(usdc_token.balance(&buyer), 600);
+    assert_eq!(client.get_insurance_balance(&usdc.address), 5 + 5 + 600);
+    let trade = client.get_trade(&failed);
+    assert_eq!(trade.insured_loss, 400);
+    assert_eq!(trade.insurance_paid, 0);
+
+    assert_eq!(
+        client.try_claim_insurance(&vendor, &failed),
+        Err(Ok(P2PError::NotTradeBuyer))
+    );
+    assert_eq!(client.claim_insurance(&buyer, &failed), 400);
+    assert_eq!(usdc_token.balance(&buyer), 1000);
+    assert_eq!(client.get_insurance_balance(&usdc.address), 210);
+    assert_eq!(client.get_trade(&failed).insurance_paid, 400);
+
+    assert_eq!(
+        client.try_claim_insurance(&buyer, &failed),
+        Err(Ok(P2PError::NothingToWithdraw))
+    );
+}
+
+#[test]
+fn test_insurance_values_slash_in_other_token() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|li| li.timestamp = 10_000);
+
+    let admin = Address::generate(&env);
+    let arbiter = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+    let (xlm, xlm_token) = create_token_contract(&env, &token_admin);
+    let oracle = MockOracleClient::new(&env, &env.register(MockOracle, ()));
+
+    // 2.5% fee; half of every slash and all fees go to insurance
+    client.initialize(&admin, &120, &86400, &604800, &250, &admin);
+    client.register_token(&admin, &usdc.address);
+    client.register_token(&admin, &xlm.address);
+    client.register_arbiter(&admin, &arbiter);
+    client.set_insurance_config(&admin, &5000, &10000);
+    client.set_oracle(&admin, &oracle.address, &300);
+    oracle.set_price(&usdc.address, &100_000_000_000_000, &10_000);     // $1.00
+    oracle.set_price(&xlm.address, &10_000_000_000_000, &10_000);       // $0.10
+
+    xlm.mint(&vendor, &3600_0000000);
+    usdc.mint(&buyer, &3000_0000000);
+
+    // Paid in USDC, collateralised in XLM
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::CrossChain,
+        &String::from_str(&env, "USDC to ETH"),
+        &String::from_str(&env, "Swap via HTLC"),
+        &1000_0000000,
+        &3,
+        &usdc.address,
+        &3600_0000000,
+        &xlm.address,
+    );
+
+    let preimage = BytesN::from_array(&env, &[7u8; 32]);
+    let hashlock: BytesN<32> = env.crypto().sha256(&Bytes::from(preimage.clone())).to_bytes();
+
+    // Completed trade puts its 25 USDC fee in the fund
+    let completed = client.accept_offer(&buyer, &offer_id, &1, &None, &Some(hashlock.clone()), &None, &None);
+    client.claim_htlc(&vendor, &completed, &preimage);
+    client.confirm_delivery(&buyer, &completed);
+    assert_eq!(client.get_insurance_balance(&usdc.address), 25_0000000);
+
+    let failed = client.accept_offer(&buyer, &offer_id, &1, &None, &Some(hashlock.clone()), &None, &None);
+    client.claim_htlc(&vendor, &failed, &preimage);
+    client.dispute_trade(&buyer, &failed, &String::from_str(&env, "ETH never arrived"));
+    client.resolve_dispute(&arbiter, &failed, &10000, &10000);
+
+    // 1200 XLM slashed: 600 to the buyer, worth $60, and 600 to the XLM fund
+    assert_eq!(xlm_token.balance(&buyer), 600_0000000);
+    assert_eq!(client.get_insurance_balance(&xlm.address), 600_0000000);
+    assert_eq!(client.get_trade(&failed).insured_loss, 940_0000000);
+
+    // The claim is paid from the USDC fund, which holds both trades' fees
+    assert_eq!(client.claim_insurance(&buyer, &failed), 50_0000000);
+    assert_eq!(usdc_token.balance(&buyer), 1050_0000000);
+
+    // Once the price is stale the slash can't be valued, so nothing is claimable
+    env.ledger().with_mut(|li| li.timestamp += 301);
+    let unpriced = client.accept_offer(&buyer, &offer_id, &1, &None, &Some(hashlock), &None, &None);
+    client.claim_htlc(&vendor, &unpriced, &preimage);
+    client.dispute_trade(&buyer, &unpriced, &String::from_str(&env, "ETH never arrived"));
+    client.resolve_dispute(&arbiter, &unpriced, &10000, &10000);
+
+    assert_eq!(xlm_token.balance(&buyer), 1200_0000000);
+    assert_eq!(client.get_trade(&unpriced).insured_loss, 0);
+}
+
+#[test]
+fn test_insurance_excludes_released_milestones() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let arbiter = Address::generate(&env);
+    let vendor = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+
+    let client = create_p2p_contract(&env);
+    let (usdc, usdc_token) = create_token_contract(&env, &token_admin);
+
+    client.initialize(&admin, &120, &86400, &604800, &0, &admin);
+    client.register_token(&admin, &usdc.address);
+    client.register_arbiter(&admin, &arbiter);
+    client.set_insurance_config(&admin, &10000, &0);
+
+    usdc.mint(&vendor, &1200);
+    usdc.mint(&buyer, &1000);
+
+    let offer_id = client.create_offer(
+        &vendor,
+        &Category::Services,
+        &String::from_str(&env, "Logo design"),
+        &String::from_str(&env, "Sketches, then final files"),
+        &1000,
+        &1,
+        &usdc.address,
+        &1200,
+        &usdc.address,
+    );
+    client.set_milestones(&vendor, &offer_id, &vec![&env, 5000, 5000]);
+
+    let trade_id = client.accept_offer(&buyer, &offer_id, &1, &None, &None, &None, &None);
+    client.mark_delivered(&vendor, &trade_id, &String::from_str(&env, "Sketches"));
+    client.confirm_delivery(&buyer, &trade_id);
+
+    client.mark_delivered(&vendor, &trade_id, &String::from_str(&env, "Final files"));
+    client.dispute_trade(&buyer, &trade_id, &String::from_str(&env, "Files never arrived"));
+    client.resolve_dispute(&arbiter, &trade_id, &10000, &10000);
+
+    // The refund covers the unreleased half in full; the confirmed milestone
+    // isn't claimable even though the whole slash sits in the fund
+    assert_eq!(usdc_token.balance(&buyer), 500);
+    assert_eq!(client.get_insurance_balance(&usdc.address), 600);
+    assert_eq!(client.get_trade(&trade_id).insured_loss, 0);
+    assert_eq!(
+        client.try_claim_insurance(&buyer, &trade_id),
+        Err(Ok(P2PError::NothingToWithdraw))
+    );
 }
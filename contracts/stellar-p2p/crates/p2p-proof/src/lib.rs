@@ -0,0 +1,198 @@
+//! Host-side helpers for sealed delivery proofs in p2p-core.
+//!
+//! A vendor seals a gift-card PIN or airtime code to the buyer's X25519 key with
+//! [`seal`] and submits the envelope and commitment to `mark_delivered_sealed`.
+//! The buyer recovers the proof with [`open`]. If a dispute comes up, the buyer
+//! or vendor discloses `(proof, salt)` and an arbiter checks it with [`verify`].
+//!
+//! Envelope layout: `version (1) || ephemeral key (32) || nonce (12) || ciphertext`.
+//! The plaintext is `salt (32) || proof`, and the commitment is `sha256(proof || salt)`.
+
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use rand_core::{CryptoRng, OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+// ============================================================================
+// CONSTANTS
+// ============================================================================
+
+pub const ENVELOPE_VERSION: u8 = 1;
+pub const KEY_LEN: usize = 32;
+pub const SALT_LEN: usize = 32;
+pub const NONCE_LEN: usize = 12;
+pub const TAG_LEN: usize = 16;
+
+const HEADER_LEN: usize = 1 + KEY_LEN + NONCE_LEN;
+const KDF_INFO: &[u8] = b"p2p-core sealed proof v1";
+
+// ============================================================================
+// TYPES
+// ============================================================================
+
+/// Envelope plus the commitment to store on-chain next to it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sealed {
+    pub envelope: Vec<u8>,
+    pub commitment: [u8; 32],
+}
+
+/// Decrypted envelope contents
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Opened {
+    pub proof: Vec<u8>,
+    pub salt: [u8; SALT_LEN],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnvelopeError {
+    /// Envelope is shorter than the header plus tag
+    Malformed,
+    /// Envelope was produced by an unknown format version
+    UnsupportedVersion(u8),
+    /// Wrong key, or the envelope was tampered with
+    DecryptionFailed,
+}
+
+impl core::fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            EnvelopeError::Malformed => write!(f, "malformed envelope"),
+            EnvelopeError::UnsupportedVersion(v) => write!(f, "unsupported envelope version {v}"),
+            EnvelopeError::DecryptionFailed => write!(f, "envelope decryption failed"),
+        }
+    }
+}
+
+impl std::error::Error for EnvelopeError {}
+
+// ============================================================================
+// KEYS
+// ============================================================================
+
+/// New buyer keypair; the public half goes to `accept_offer`
+pub fn generate_keypair() -> ([u8; KEY_LEN], [u8; KEY_LEN]) {
+    let secret = StaticSecret::random_from_rng(OsRng);
+    let public = PublicKey::from(&secret);
+    (secret.to_bytes(), public.to_bytes())
+}
+
+/// Public key for a stored secret
+pub fn public_key(secret: &[u8; KEY_LEN]) -> [u8; KEY_LEN] {
+    PublicKey::from(&StaticSecret::from(*secret)).to_bytes()
+}
+
+// ============================================================================
+// SEAL / OPEN
+// ============================================================================
+
+/// Seal `proof` to the buyer's public key with fresh randomness
+pub fn seal(recipient: &[u8; KEY_LEN], proof: &[u8]) -> Sealed {
+    seal_with_rng(recipient, proof, &mut OsRng)
+}
+
+/// Seal with a caller-supplied RNG
+pub fn seal_with_rng<R: RngCore + CryptoRng>(recipient: &[u8; KEY_LEN], proof: &[u8], rng: &mut R) -> Sealed {
+    let recipient = PublicKey::from(*recipient);
+    let ephemeral = EphemeralSecret::random_from_rng(&mut *rng);
+    let ephemeral_public = PublicKey::from(&ephemeral);
+    let shared = ephemeral.diffie_hellman(&recipient);
+
+    let mut salt = [0u8; SALT_LEN];
+    rng.fill_bytes(&mut salt);
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill_bytes(&mut nonce);
+
+    let cipher = cipher_for(shared.as_bytes(), &ephemeral_public, &recipient);
+
+    let mut plaintext = Vec::with_capacity(SALT_LEN + proof.len());
+    plaintext.extend_from_slice(&salt);
+    plaintext.extend_from_slice(proof);
+
+    // Encryption with a valid key and nonce length cannot fail
+    let ciphertext = cipher
+        .encrypt(&Nonce::from(nonce), plaintext.as_slice())
+        .expect("chacha20poly1305 encryption");
+
+    let mut envelope = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+    envelope.push(ENVELOPE_VERSION);
+    envelope.extend_from_slice(ephemeral_public.as_bytes());
+    envelope.extend_from_slice(&nonce);
+    envelope.extend_from_slice(&ciphertext);
+
+    Sealed {
+        envelope,
+        commitment: commitment(proof, &salt),
+    }
+}
+
+/// Open an envelope with the buyer's secret key
+pub fn open(secret: &[u8; KEY_LEN], envelope: &[u8]) -> Result<Opened, EnvelopeError> {
+    if envelope.len() < HEADER_LEN + SALT_LEN + TAG_LEN {
+        return Err(EnvelopeError::Malformed);
+    }
+    if envelope[0] != ENVELOPE_VERSION {
+        return Err(EnvelopeError::UnsupportedVersion(envelope[0]));
+    }
+
+    let mut ephemeral = [0u8; KEY_LEN];
+    ephemeral.copy_from_slice(&envelope[1..1 + KEY_LEN]);
+    let ephemeral_public = PublicKey::from(ephemeral);
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce.copy_from_slice(&envelope[1 + KEY_LEN..HEADER_LEN]);
+
+    let secret = StaticSecret::from(*secret);
+    let recipient = PublicKey::from(&secret);
+    let shared = secret.diffie_hellman(&ephemeral_public);
+
+    let plaintext = cipher_for(shared.as_bytes(), &ephemeral_public, &recipient)
+        .decrypt(&Nonce::from(nonce), &envelope[HEADER_LEN..])
+        .map_err(|_| EnvelopeError::DecryptionFailed)?;
+
+    let mut salt = [0u8; SALT_LEN];
+    salt.copy_from_slice(&plaintext[..SALT_LEN]);
+
+    Ok(Opened {
+        proof: plaintext[SALT_LEN..].to_vec(),
+        salt,
+    })
+}
+
+// ============================================================================
+// COMMITMENTS
+// ============================================================================
+
+/// sha256(proof || salt), as stored by the contract
+pub fn commitment(proof: &[u8], salt: &[u8; SALT_LEN]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(proof);
+    hasher.update(salt);
+    hasher.finalize().into()
+}
+
+/// Check a disclosed proof against an on-chain commitment
+pub fn verify(proof: &[u8], salt: &[u8; SALT_LEN], expected: &[u8; 32]) -> bool {
+    commitment(proof, salt) == *expected
+}
+
+// ============================================================================
+// INTERNAL HELPERS
+// ============================================================================
+
+/// Derive the AEAD key from the shared secret, bound to both public keys
+fn cipher_for(shared: &[u8; 32], ephemeral: &PublicKey, recipient: &PublicKey) -> ChaCha20Poly1305 {
+    let mut kdf_salt = [0u8; 2 * KEY_LEN];
+    kdf_salt[..KEY_LEN].copy_from_slice(ephemeral.as_bytes());
+    kdf_salt[KEY_LEN..].copy_from_slice(recipient.as_bytes());
+
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&kdf_salt), shared)
+        .expand(KDF_INFO, &mut key)
+        .expect("32-byte HKDF output");
+
+    ChaCha20Poly1305::new(&Key::from(key))
+}
+
+mod test;
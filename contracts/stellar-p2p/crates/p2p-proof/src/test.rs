@@ -0,0 +1,63 @@
+#![cfg(test)]
+
+use super::*;
+
+#[test]
+fn test_seal_and_open_round_trip() {
+    let (secret, public) = generate_keypair();
+    assert_eq!(public_key(&secret), public);
+
+    let sealed = seal(&public, b"AMZN-4821-XKQP-7730");
+    let opened = open(&secret, &sealed.envelope).unwrap();
+
+    assert_eq!(opened.proof, b"AMZN-4821-XKQP-7730");
+    assert!(verify(&opened.proof, &opened.salt, &sealed.commitment));
+}
+
+#[test]
+fn test_envelope_hides_proof() {
+    let (_, public) = generate_keypair();
+    let proof = b"MTN-PIN-5531902241";
+
+    let first = seal(&public, proof);
+    let second = seal(&public, proof);
+
+    // Fresh salt and ephemeral key every time
+    assert_ne!(first.envelope, second.envelope);
+    assert_ne!(first.commitment, second.commitment);
+    assert!(!first.envelope.windows(proof.len()).any(|w| w == proof));
+}
+
+#[test]
+fn test_wrong_key_cannot_open() {
+    let (_, public) = generate_keypair();
+    let (other_secret, _) = generate_keypair();
+
+    let sealed = seal(&public, b"CODE");
+    assert_eq!(open(&other_secret, &sealed.envelope), Err(EnvelopeError::DecryptionFailed));
+}
+
+#[test]
+fn test_tampered_envelope_rejected() {
+    let (secret, public) = generate_keypair();
+    let mut sealed = seal(&public, b"CODE");
+
+    let last = sealed.envelope.len() - 1;
+    sealed.envelope[last] ^= 0x01;
+    assert_eq!(open(&secret, &sealed.envelope), Err(EnvelopeError::DecryptionFailed));
+
+    sealed.envelope[0] = 9;
+    assert_eq!(open(&secret, &sealed.envelope), Err(EnvelopeError::UnsupportedVersion(9)));
+
+    assert_eq!(open(&secret, &sealed.envelope[..20]), Err(EnvelopeError::Malformed));
+}
+
+#[test]
+fn test_commitment_rejects_wrong_disclosure() {
+    let (secret, public) = generate_keypair();
+    let sealed = seal(&public, b"REAL-CODE");
+    let opened = open(&secret, &sealed.envelope).unwrap();
+
+    assert!(!verify(b"FAKE-CODE", &opened.salt, &sealed.commitment));
+    assert!(!verify(&opened.proof, &[0u8; SALT_LEN], &sealed.commitment));
+}
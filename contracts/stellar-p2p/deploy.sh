@@ -29,7 +29,9 @@ stellar contract invoke \
   --admin "$(stellar keys address default)" \
   --min_collateral_percent 120 \
   --auto_complete_window 86400 \
-  --dispute_window 604800
+  --dispute_window 604800 \
+  --fee_bps 100 \
+  --treasury "$(stellar keys address default)"
 
 echo "✅ Contract initialized"
 
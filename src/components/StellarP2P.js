@@ -120,6 +120,33 @@ const StellarP2P = () => {
     },
   ];
 
+  // Mirrors the contract's coll_add / coll_wd events: (amount, locked total, free)
+  const collateralEvents = {
+    coll_add: { label: 'Collateral added', sign: '+', color: 'text-green-400' },
+    coll_wd: { label: 'Collateral withdrawn', sign: '-', color: 'text-orange-400' },
+  };
+
+  const collateralActivity = [
+    {
+      id: 1,
+      event: 'coll_add',
+      offerId: 1,
+      amount: 60,
+      locked: 120,
+      free: 60,
+      time: '1 hour ago'
+    },
+    {
+      id: 2,
+      event: 'coll_wd',
+      offerId: 3,
+      amount: 12,
+      locked: 36,
+      free: 0,
+      time: '3 hours ago'
+    },
+  ];
+
   const stats = {
     totalVolume: '$127.5K',
     totalTrades: 1243,
@@ -406,6 +433,32 @@ const StellarP2P = () => {
             </div>
           )}
 
+          {activeTab === 'my-offers' && collateralActivity.length > 0 && (
+            <div className="mb-8 bg-white/5 border border-white/10 rounded-2xl p-6 backdrop-blur-sm">
+              <div className="flex items-center space-x-2 mb-4">
+                <Shield className="w-5 h-5 text-purple-400" />
+                <h3 className="text-lg font-bold text-white">Collateral Activity</h3>
+              </div>
+              <div className="space-y-3">
+                {collateralActivity.map((entry) => {
+                  const kind = collateralEvents[entry.event];
+                  return (
+                    <div key={entry.id} className="flex items-center justify-between text-sm">
+                      <div className="text-gray-300">
+                        <span className="font-medium text-white">{kind.label}</span>
+                        <span className="text-gray-400"> · Offer #{entry.offerId} · {entry.time}</span>
+                      </div>
+                      <div className="flex items-center space-x-4">
+                        <span className={`font-bold ${kind.color}`}>{kind.sign}${entry.amount}</span>
+                        <span className="text-gray-400">Locked ${entry.locked} · Free ${entry.free}</span>
+                      </div>
+                    </div>
+                  );
+                })}
+              </div>
+            </div>
+          )}
+
           {activeTab === 'my-offers' && (
             <div className="text-center py-20">
               <div className="w-20 h-20 bg-gradient-to-br from-purple-500 to-blue-500 rounded-2xl flex items-center justify-center mx-auto mb-6">